@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tide_basic_crud::{BatchRequest, Dino, NewDino};
+
+// Every body the dino endpoints deserialize is either rejected or read back unchanged.
+fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(data: &[u8]) {
+    if let Ok(value) = serde_json::from_slice::<T>(data) {
+        let json = serde_json::to_value(&value).unwrap();
+        let again: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(json, serde_json::to_value(&again).unwrap());
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    round_trip::<NewDino>(data);
+    round_trip::<Dino>(data);
+    round_trip::<BatchRequest>(data);
+    // the patch bodies
+    round_trip::<json_patch::Patch>(data);
+});
@@ -0,0 +1,108 @@
+-- The schema before migrations were kept. Every statement is idempotent so databases
+-- created by hand before then can be migrated too.
+
+-- gen_random_uuid() before Postgres 13
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+CREATE TABLE IF NOT EXISTS dinos (
+    id uuid PRIMARY KEY,
+    name text NOT NULL,
+    weight integer NOT NULL,
+    diet text NOT NULL,
+    user_id text
+);
+
+ALTER TABLE dinos ADD COLUMN IF NOT EXISTS deleted_at timestamp with time zone;
+
+CREATE INDEX IF NOT EXISTS dinos_deleted_at_idx ON dinos (deleted_at) WHERE deleted_at IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS dino_audit (
+    id uuid PRIMARY KEY,
+    dino_id uuid NOT NULL,
+    action text NOT NULL,
+    actor_id text,
+    request_id text,
+    changed_at timestamp with time zone DEFAULT clock_timestamp() NOT NULL,
+    before jsonb,
+    after jsonb,
+    diff jsonb NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS dino_audit_dino_id_changed_at_idx ON dino_audit (dino_id, changed_at);
+
+CREATE TABLE IF NOT EXISTS dino_revisions (
+    dino_id uuid NOT NULL,
+    rev integer NOT NULL,
+    name text NOT NULL,
+    weight integer NOT NULL,
+    diet text NOT NULL,
+    user_id text,
+    actor_id text,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY (dino_id, rev)
+);
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    key text NOT NULL,
+    user_id text DEFAULT '' NOT NULL,
+    request_body text NOT NULL,
+    status integer,
+    response_body text,
+    location text,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY (key, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idempotency_keys_created_at_idx ON idempotency_keys (created_at);
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    id uuid PRIMARY KEY,
+    user_id text DEFAULT '' NOT NULL,
+    url text NOT NULL,
+    secret text NOT NULL,
+    events text[] NOT NULL,
+    diet text,
+    created_at timestamp with time zone DEFAULT now() NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id uuid DEFAULT gen_random_uuid() PRIMARY KEY,
+    webhook_id uuid NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    event text NOT NULL,
+    dino jsonb NOT NULL,
+    status text DEFAULT 'pending' NOT NULL,
+    attempts integer DEFAULT 0 NOT NULL,
+    next_attempt_at timestamp with time zone DEFAULT now() NOT NULL,
+    last_status integer,
+    last_error text,
+    created_at timestamp with time zone DEFAULT clock_timestamp() NOT NULL,
+    delivered_at timestamp with time zone
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_pending_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_webhook_id_created_at_idx ON webhook_deliveries (webhook_id, created_at);
+
+CREATE TABLE IF NOT EXISTS jobs (
+    id uuid DEFAULT gen_random_uuid() PRIMARY KEY,
+    kind text NOT NULL,
+    payload jsonb NOT NULL,
+    status text DEFAULT 'pending' NOT NULL,
+    attempts integer DEFAULT 0 NOT NULL,
+    max_attempts integer NOT NULL,
+    run_at timestamp with time zone DEFAULT now() NOT NULL,
+    locked_at timestamp with time zone,
+    last_error text,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    finished_at timestamp with time zone
+);
+
+CREATE INDEX IF NOT EXISTS jobs_queue_idx ON jobs (run_at) WHERE status IN ('pending', 'running');
+
+CREATE TABLE IF NOT EXISTS recurring_jobs (
+    name text PRIMARY KEY,
+    kind text NOT NULL,
+    payload jsonb NOT NULL,
+    schedule text NOT NULL,
+    next_run_at timestamp with time zone NOT NULL
+);
@@ -0,0 +1,21 @@
+-- The users known from their logins, and the sessions shared by every instance. Idempotent
+-- like the initial schema.
+
+CREATE TABLE IF NOT EXISTS users (
+    id text PRIMARY KEY,
+    name text,
+    role text DEFAULT 'user' NOT NULL,
+    disabled boolean DEFAULT false NOT NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id text PRIMARY KEY,
+    user_id text,
+    session jsonb NOT NULL,
+    expires_at timestamp with time zone
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions (user_id);
+
+CREATE INDEX IF NOT EXISTS sessions_expires_at_idx ON sessions (expires_at);
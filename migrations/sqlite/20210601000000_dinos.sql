@@ -0,0 +1,12 @@
+-- The dinos only, the rest of the schema needs Postgres.
+
+CREATE TABLE IF NOT EXISTS dinos (
+    id text PRIMARY KEY,
+    name text NOT NULL,
+    weight integer NOT NULL,
+    diet text NOT NULL,
+    user_id text,
+    deleted_at text
+);
+
+CREATE INDEX IF NOT EXISTS dinos_deleted_at_idx ON dinos (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,31 @@
+use super::*;
+
+use crate::middlewares::deprecation::Deprecated;
+use crate::middlewares::errors::JsonErrors;
+use crate::middlewares::MatchedRoute;
+
+pub mod v1;
+
+pub type Routes = Vec<(Method, &'static str, Box<dyn Endpoint<State>>)>;
+
+// Mounts a version of the JSON api under `prefix`, e.g. `/api/v1`, its errors with a JSON body.
+pub fn mount(app: &mut Server<State>, prefix: &str, routes: Routes) {
+    for (method, path, endpoint) in routes {
+        let path = format!("{}{}", prefix, path);
+        app.at(&path)
+            .with(MatchedRoute::new(&path))
+            .with(JsonErrors)
+            .method(method, endpoint);
+    }
+}
+
+// Mounts `routes` without a prefix, as deprecated aliases of the ones under `successor`.
+pub fn mount_deprecated(app: &mut Server<State>, successor: &'static str, routes: Routes) {
+    for (method, path, endpoint) in routes {
+        app.at(path)
+            .with(MatchedRoute::new(path))
+            .with(Deprecated::new(successor))
+            .with(JsonErrors)
+            .method(method, endpoint);
+    }
+}
@@ -0,0 +1,135 @@
+use super::*;
+
+use crate::controllers::{socket, webhook};
+use crate::idempotency::idempotent;
+use crate::websocket::websocket;
+
+pub static PREFIX: &str = "/api/v1";
+
+// the routes served before the api was versioned, kept at their old paths as deprecated aliases
+pub static LEGACY_ROUTES: &[(Method, &str)] = &[
+    (Method::Get, "/dinos"),
+    (Method::Post, "/dinos"),
+    (Method::Get, "/dinos/:id"),
+    (Method::Put, "/dinos/:id"),
+    (Method::Delete, "/dinos/:id"),
+];
+
+// the routes that only need the dino repository, the ones a standalone app serves
+pub static REPOSITORY_ROUTES: &[(Method, &str)] = &[
+    (Method::Get, "/dinos"),
+    (Method::Post, "/dinos"),
+    (Method::Get, "/dinos/export"),
+    (Method::Get, "/dinos/trash"),
+    (Method::Get, "/dinos/:id"),
+    (Method::Put, "/dinos/:id"),
+    (Method::Patch, "/dinos/:id"),
+    (Method::Delete, "/dinos/:id"),
+    (Method::Post, "/dinos/:id/restore"),
+];
+
+// The v1 JSON api, kept as a table so the OpenAPI document can be generated from it.
+pub fn routes() -> Routes {
+    vec![
+        (
+            Method::Get,
+            "/dinos",
+            Box::new(traced("dino::list", dino::list)),
+        ),
+        (
+            Method::Post,
+            "/dinos",
+            Box::new(traced("dino::create", idempotent(dino::create))),
+        ),
+        (
+            Method::Get,
+            "/dinos/export",
+            Box::new(traced("dino::export", dino::export)),
+        ),
+        (
+            Method::Get,
+            "/dinos/events",
+            Box::new(traced("dino::events", tide::sse::endpoint(dino::events))),
+        ),
+        (
+            Method::Get,
+            "/dinos/ws",
+            Box::new(traced("dino::socket", websocket(socket::dinos))),
+        ),
+        (
+            Method::Get,
+            "/dinos/trash",
+            Box::new(traced("dino::trash", dino::trash)),
+        ),
+        (
+            Method::Post,
+            "/dinos/batch",
+            Box::new(traced("dino::batch", dino::batch)),
+        ),
+        (
+            Method::Post,
+            "/dinos/import",
+            Box::new(traced("dino::import", dino::import)),
+        ),
+        (
+            Method::Get,
+            "/dinos/:id",
+            Box::new(traced("dino::get", dino::get)),
+        ),
+        (
+            Method::Put,
+            "/dinos/:id",
+            Box::new(traced("dino::update", dino::update)),
+        ),
+        (
+            Method::Patch,
+            "/dinos/:id",
+            Box::new(traced("dino::patch", dino::patch)),
+        ),
+        (
+            Method::Delete,
+            "/dinos/:id",
+            Box::new(traced("dino::delete", dino::delete)),
+        ),
+        (
+            Method::Get,
+            "/dinos/:id/history",
+            Box::new(traced("dino::history", dino::history)),
+        ),
+        (
+            Method::Get,
+            "/dinos/:id/revisions",
+            Box::new(traced("dino::revisions", dino::revisions)),
+        ),
+        (
+            Method::Post,
+            "/dinos/:id/revisions/:rev/revert",
+            Box::new(traced("dino::revert", dino::revert)),
+        ),
+        (
+            Method::Post,
+            "/dinos/:id/restore",
+            Box::new(traced("dino::restore", dino::restore)),
+        ),
+        (
+            Method::Get,
+            "/webhooks",
+            Box::new(traced("webhook::list", webhook::list)),
+        ),
+        (
+            Method::Post,
+            "/webhooks",
+            Box::new(traced("webhook::create", webhook::create)),
+        ),
+        (
+            Method::Delete,
+            "/webhooks/:id",
+            Box::new(traced("webhook::delete", webhook::delete)),
+        ),
+        (
+            Method::Get,
+            "/webhooks/:id/deliveries",
+            Box::new(traced("webhook::deliveries", webhook::deliveries)),
+        ),
+    ]
+}
@@ -0,0 +1,280 @@
+use super::*;
+
+use async_std::io::{BufReader, WriteExt};
+use async_std::stream::StreamExt;
+use serde_json::Value;
+use sqlx::migrate::Migrator;
+
+use crate::controllers::dino::import_rows;
+use crate::export::{Encoder, ExportFormat};
+use crate::handlers::audit::Actor;
+use crate::handlers::user::ADMIN;
+use crate::import::{DinoRows, ImportFormat};
+
+pub static USAGE: &str = "\
+Usage: tide-basic-crud [--json] [command]
+
+Commands:
+  serve                      serve the app and run the background jobs (the default)
+  worker                     only run the background jobs
+  migrate                    apply the pending database migrations
+  seed                       insert the bundled dinosaur species that are missing
+  import <file>              import dinos from a csv or ndjson file
+      [--format csv|ndjson] [--upsert] [--dry-run]
+  export [--format csv|ndjson|json]
+                             write every dino to stdout, csv by default
+  user create <id> [--name <name>] [--admin]
+  user promote <id>          make the user an admin
+  user disable <id>          prevent the user from logging in and log them out
+  sessions purge [--all]     delete the expired sessions, or all of them
+
+  --json                     print the result of the command as JSON";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Serve,
+    Worker,
+    Migrate,
+    Seed,
+    Import {
+        path: String,
+        format: Option<ImportFormat>,
+        upsert: bool,
+        dry_run: bool,
+    },
+    Export {
+        format: ExportFormat,
+    },
+    UserCreate {
+        id: String,
+        name: Option<String>,
+        admin: bool,
+    },
+    UserPromote {
+        id: String,
+    },
+    UserDisable {
+        id: String,
+    },
+    SessionsPurge {
+        all: bool,
+    },
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub json: bool,
+}
+
+impl Cli {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut flags = vec![];
+        let mut options = std::collections::HashMap::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" | "--name" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("`{}` needs a value", arg))?;
+                    options.insert(arg, value);
+                }
+                "-h" | "--help" => flags.push("--help".to_string()),
+                flag if flag.starts_with("--") => flags.push(arg),
+                _ => positional.push(arg),
+            }
+        }
+        let flag = |name: &str| flags.iter().any(|flag| flag == name);
+        let format = |name: &str| -> Result<Option<Value>, String> {
+            Ok(options.get(name).cloned().map(Value::String))
+        };
+
+        let positional: Vec<&str> = positional.iter().map(String::as_str).collect();
+        let command = match positional.as_slice() {
+            _ if flag("--help") => Command::Help,
+            [] | ["serve"] => Command::Serve,
+            ["worker"] => Command::Worker,
+            ["migrate"] => Command::Migrate,
+            ["seed"] => Command::Seed,
+            ["import", path] => Command::Import {
+                path: path.to_string(),
+                format: format("--format")?
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .map_err(|_| "the import format is `csv` or `ndjson`".to_string())?,
+                upsert: flag("--upsert"),
+                dry_run: flag("--dry-run"),
+            },
+            ["export"] => Command::Export {
+                format: format("--format")?
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .map_err(|_| "the export format is `csv`, `ndjson` or `json`".to_string())?
+                    .unwrap_or(ExportFormat::Csv),
+            },
+            ["user", "create", id] => Command::UserCreate {
+                id: id.to_string(),
+                name: options.get("--name").cloned(),
+                admin: flag("--admin"),
+            },
+            ["user", "promote", id] => Command::UserPromote { id: id.to_string() },
+            ["user", "disable", id] => Command::UserDisable { id: id.to_string() },
+            ["sessions", "purge"] => Command::SessionsPurge { all: flag("--all") },
+            _ => return Err(format!("unknown command `{}`", positional.join(" "))),
+        };
+
+        Ok(Cli {
+            command,
+            json: flag("--json"),
+        })
+    }
+}
+
+// What a command did, for people and for scripts.
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub text: String,
+    pub json: Value,
+}
+
+impl Output {
+    fn new(text: String, json: Value) -> Self {
+        Output { text, json }
+    }
+}
+
+// Runs one of the admin commands, `serve` and `worker` are run by `main`.
+pub async fn run(command: Command, db_pool: &PgPool) -> tide::Result<Output> {
+    // the changes made from the command line are audited without a user
+    let actor = Actor::default();
+
+    let output = match command {
+        Command::Serve | Command::Worker | Command::Help => {
+            return Err(Error::from_str(500, "not an admin command"))
+        }
+        Command::Migrate => {
+            let migrator = sqlx::migrate!("./migrations/postgres");
+            migrator
+                .run(db_pool)
+                .await
+                .map_err(|e| Error::new(500, e))?;
+            migrated(&migrator)
+        }
+        Command::Seed => {
+            let report = seed::run(&actor, db_pool).await?;
+            Output::new(
+                format!(
+                    "{} dinos seeded, {} already there",
+                    report.created, report.skipped
+                ),
+                serde_json::to_value(&report)?,
+            )
+        }
+        Command::Import {
+            path,
+            format,
+            upsert,
+            dry_run,
+        } => {
+            let format = format
+                .or_else(|| match path.rsplit('.').next() {
+                    Some("csv") => Some(ImportFormat::Csv),
+                    Some("ndjson") | Some("jsonl") => Some(ImportFormat::Ndjson),
+                    _ => None,
+                })
+                .ok_or_else(|| Error::from_str(422, "can't tell the format, use `--format`"))?;
+            let file = async_std::fs::File::open(&path).await?;
+            let rows = DinoRows::new(BufReader::new(file), format, &Default::default()).await?;
+            let report = import_rows(rows, upsert, dry_run, "", &actor, db_pool).await?;
+            let text = match report.errors.first() {
+                None => format!(
+                    "{} dinos created, {} updated{}",
+                    report.created,
+                    report.updated,
+                    if report.committed { "" } else { " (dry run)" }
+                ),
+                Some(error) => format!(
+                    "nothing imported, {} rows failed, row {}: {}",
+                    report.errors.len(),
+                    error.row,
+                    error.error
+                ),
+            };
+            Output::new(text, serde_json::to_value(&report)?)
+        }
+        Command::Export { format } => {
+            let exported = export(format, db_pool).await?;
+            Output::new(
+                format!("{} dinos exported", exported),
+                json!({ "exported": exported }),
+            )
+        }
+        Command::UserCreate { id, name, admin } => {
+            let role = if admin { ADMIN } else { "user" };
+            let user = handlers::user::create(&id, name.as_deref(), role, db_pool).await?;
+            Output::new(
+                format!("user {} created", user.id),
+                serde_json::to_value(&user)?,
+            )
+        }
+        Command::UserPromote { id } => {
+            let user = handlers::user::set_role(&id, ADMIN, db_pool)
+                .await?
+                .ok_or_else(|| Error::from_str(404, format!("user {} not found", id)))?;
+            Output::new(
+                format!("user {} is an admin", user.id),
+                serde_json::to_value(&user)?,
+            )
+        }
+        Command::UserDisable { id } => {
+            let mut tx = db_pool.begin().await?;
+            let user = handlers::user::set_disabled(&id, true, &mut tx)
+                .await?
+                .ok_or_else(|| Error::from_str(404, format!("user {} not found", id)))?;
+            let sessions = handlers::session::purge_user(&id, &mut tx).await?;
+            tx.commit().await?;
+            Output::new(
+                format!("user {} disabled, {} sessions ended", user.id, sessions),
+                json!({ "user": user, "sessions_purged": sessions }),
+            )
+        }
+        Command::SessionsPurge { all } => {
+            let purged = handlers::session::purge(all, db_pool).await?;
+            Output::new(
+                format!("{} sessions purged", purged),
+                json!({ "purged": purged }),
+            )
+        }
+    };
+
+    Ok(output)
+}
+
+pub fn migrated(migrator: &Migrator) -> Output {
+    let versions: Vec<i64> = migrator.iter().map(|m| m.version).collect();
+    Output::new(
+        format!("{} migrations applied", versions.len()),
+        json!({ "migrations": versions }),
+    )
+}
+
+// Writes every dino to stdout, returns how many.
+async fn export(format: ExportFormat, db_pool: &PgPool) -> tide::Result<usize> {
+    let mut stdout = async_std::io::stdout();
+    let mut encoder = Encoder::new(format);
+    stdout.write_all(&encoder.header()?).await?;
+
+    let mut exported = 0;
+    let mut rows = handlers::dino::stream(db_pool);
+    while let Some(dino) = rows.next().await {
+        stdout.write_all(&encoder.row(&dino?)?).await?;
+        exported += 1;
+    }
+    stdout.write_all(&encoder.footer()).await?;
+    stdout.flush().await?;
+
+    Ok(exported)
+}
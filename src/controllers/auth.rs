@@ -1,64 +1,49 @@
 use super::*;
 
-use tide::{http, Redirect, Request, Result};
+use tide::{Redirect, Request, Result};
 
-use oauth2::reqwest::async_http_client;
-use oauth2::{AuthorizationCode, CsrfToken, Scope, TokenResponse};
-
-// use surf;
-
-// static AUTH_GOOGLE_SCOPE_EMAIL: &str = "https://www.googleapis.com/auth/userinfo.email";
-static AUTH_GOOGLE_SCOPE_PROFILE: &str = "https://www.googleapis.com/auth/userinfo.profile";
+use crate::handlers;
+use crate::metrics::OAUTH_LOGINS_TOTAL;
 
 #[derive(Debug, Deserialize)]
 struct AuthRequestQuery {
     code: String,
-    state: String,
-    scope: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct UserInfoResponse {
-    // email: String,
-    id: String,
-    given_name: String,
 }
 
 pub async fn auth_google(req: Request<State>) -> Result {
-    let client = &req.state().oauth_google_client;
-    let (auth_url, _csrf_token) = client
-        .authorize_url(CsrfToken::new_random)
-        // Set the desired scopes.
-        // .add_scope(Scope::new(AUTH_GOOGLE_SCOPE_EMAIL.to_string()))
-        .add_scope(Scope::new(AUTH_GOOGLE_SCOPE_PROFILE.to_string()))
-        .url();
+    let auth_url = req.state().auth.authorize_url();
 
     Ok(Redirect::see_other(auth_url).into())
 }
 
 pub async fn auth_google_authorized(mut req: Request<State>) -> Result {
-    let client = &req.state().oauth_google_client;
+    let provider = req.state().auth.clone();
     let query: AuthRequestQuery = req.query()?;
-    let token_result = client
-        .exchange_code(AuthorizationCode::new(query.code))
-        .request_async(async_http_client)
-        .await;
 
-    let token_result = match token_result {
-        Ok(token) => token,
-        Err(_) => return Err(tide::Error::from_str(401, "error")),
+    let userinfo = match provider.user_info(&query.code).await {
+        Ok(userinfo) => userinfo,
+        Err(e) => {
+            OAUTH_LOGINS_TOTAL
+                .with_label_values(&[provider.name(), "failure"])
+                .inc();
+            return Err(e);
+        }
     };
 
-    let userinfo: UserInfoResponse = surf::get("https://www.googleapis.com/oauth2/v2/userinfo")
-        .header(
-            http::headers::AUTHORIZATION,
-            format!("Bearer {}", token_result.access_token().secret()),
-        )
-        .recv_json()
-        .await?;
+    let user = handlers::user::login(&userinfo.id, &userinfo.name, &req.state().db_pool).await?;
+    if user.disabled {
+        OAUTH_LOGINS_TOTAL
+            .with_label_values(&[provider.name(), "disabled"])
+            .inc();
+        return Err(tide::Error::from_str(403, "this account is disabled"));
+    }
+
+    OAUTH_LOGINS_TOTAL
+        .with_label_values(&[provider.name(), "success"])
+        .inc();
 
     let session = req.session_mut();
-    session.insert("user_name", userinfo.given_name)?;
+    session.insert("user_name", userinfo.name)?;
     session.insert("user_id", userinfo.id)?;
 
     Ok(Redirect::new("/").into())
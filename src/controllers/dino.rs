@@ -1,12 +1,55 @@
 use super::*;
 
-use tide::{Body, Request, Response};
+use tide::{http, Body, Request, Response};
 
+use crate::controllers::views;
+use crate::export::{ChunkReader, Encoder, ExportFormat, ExportQuery};
 use crate::handlers;
+use crate::handlers::audit::Actor;
+use crate::import::{DinoRows, ImportError, ImportFormat, ImportQuery, ImportReport};
+use crate::middlewares::request_log::RequestId;
+use crate::negotiation::{negotiate, not_acceptable};
+use crate::{api, BatchOperation, BatchRequest, BatchResponse, BatchResult, DinoChanges, NewDino};
+use async_std::stream::StreamExt;
+use opentelemetry::trace::FutureExt;
+use sqlx::{Connection, PgConnection};
+
+static MERGE_PATCH: &str = "application/merge-patch+json";
+static JSON_PATCH: &str = "application/json-patch+json";
+
+// the representations of the dino list, the first one is the default
+static LIST_MEDIA_TYPES: &[&str] = &["application/json", "text/html", "text/csv"];
+
+// the rows encoded ahead of the client during an export
+static EXPORT_BUFFER: usize = 64;
+
+// dinos without an owner can be changed by anyone
+fn can_modify(owner: &Option<String>, user_id: &str) -> bool {
+    match owner {
+        Some(owner) => owner == user_id,
+        None => true,
+    }
+}
+
+// the logged in user making a change, if any
+pub fn actor(req: &Request<State>) -> Actor {
+    Actor {
+        user_id: req.session().get("user_id"),
+        request_id: req.ext::<RequestId>().map(|id| id.0.clone()),
+    }
+}
 
 pub async fn create(mut req: Request<State>) -> tide::Result {
-    let mut dino: Dino = req.body_json().await?;
-    let db_pool = req.state().db_pool.clone();
+    let new_dino: NewDino = req.body_json().await?;
+    let dinos = req.state().dinos.clone();
+
+    let mut dino = Dino {
+        id: new_dino.id.unwrap_or_else(Uuid::new_v4),
+        name: new_dino.name,
+        weight: new_dino.weight,
+        diet: new_dino.diet,
+        user_id: None,
+    };
 
     let session = req.session();
     match session.get("user_id") {
@@ -14,26 +57,112 @@ pub async fn create(mut req: Request<State>) -> tide::Result {
         None => dino.user_id = None,
     };
 
-    let row = handlers::dino::create(dino, &db_pool).await?;
+    let row = dinos.create(dino, &actor(&req)).await?;
 
     let mut res = Response::new(201);
+    res.insert_header(
+        http::headers::LOCATION,
+        format!("{}/dinos/{}", api::v1::PREFIX, row.id),
+    );
     res.set_body(Body::from_json(&row)?);
     Ok(res)
 }
 
 pub async fn list(req: Request<State>) -> tide::Result {
-    let db_pool = req.state().db_pool.clone();
-    let rows = handlers::dino::list(&db_pool).await?;
+    let media_type = match negotiate(&req, LIST_MEDIA_TYPES) {
+        None => return Ok(not_acceptable(LIST_MEDIA_TYPES)),
+        Some(media_type) => media_type,
+    };
+
+    let mut res = match media_type {
+        "text/csv" => Response::builder(200)
+            .body(export_body(&req, ExportFormat::Csv)?)
+            .build(),
+        "text/html" => views::index(req).await?,
+        _ => {
+            let rows = req.state().dinos.list().await?;
+
+            let mut res = Response::new(200);
+            res.set_body(Body::from_json(&rows)?);
+            res
+        }
+    };
+    res.append_header(http::headers::VARY, "Accept");
+    Ok(res)
+}
+
+// Streams the changes of the dinos as server-sent events named after their kind.
+pub async fn events(req: Request<State>, sender: tide::sse::Sender) -> tide::Result<()> {
+    let events = req.state().events.subscribe();
+    while let Ok(event) = events.recv().await {
+        sender
+            .send(
+                event.kind.as_str(),
+                serde_json::to_string(&event.dino)?,
+                None,
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn export(req: Request<State>) -> tide::Result {
+    let query: ExportQuery = req.query()?;
 
     let mut res = Response::new(200);
-    res.set_body(Body::from_json(&rows)?);
+    res.insert_header(
+        "Content-Disposition",
+        format!(
+            "attachment; filename=\"dinos.{}\"",
+            query.format.extension()
+        ),
+    );
+    res.set_body(export_body(&req, query.format)?);
     Ok(res)
 }
 
+// Streams every dino in the given format
+fn export_body(req: &Request<State>, format: ExportFormat) -> tide::Result<Body> {
+    let dinos = req.state().dinos.clone();
+
+    let mut encoder = Encoder::new(format);
+    let (sender, receiver) = async_std::channel::bounded(EXPORT_BUFFER);
+    let header = encoder.header()?;
+
+    // the rows are sent as the client reads them, the export stops if it goes away
+    async_std::task::spawn(
+        async move {
+            if sender.send(header).await.is_err() {
+                return;
+            }
+            let mut rows = dinos.stream();
+            while let Some(row) = rows.next().await {
+                let chunk = match row.and_then(|dino| encoder.row(&dino)) {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        tide::log::error!("export failed", { error: e.to_string() });
+                        return;
+                    }
+                };
+                if sender.send(chunk).await.is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(encoder.footer()).await;
+        }
+        .with_current_context(),
+    );
+
+    let mut body = Body::from_reader(ChunkReader::new(receiver), None);
+    body.set_mime(format.mime());
+    Ok(body)
+}
+
 pub async fn get(req: tide::Request<State>) -> tide::Result {
-    let db_pool = req.state().db_pool.clone();
-    let id: Uuid = Uuid::parse_str(req.param("id")?).unwrap();
-    let row = handlers::dino::get(id, &db_pool).await?;
+    let dinos = req.state().dinos.clone();
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
+    let row = dinos.get(id).await?;
 
     let res = match row {
         None => Response::new(404),
@@ -49,21 +178,181 @@ pub async fn get(req: tide::Request<State>) -> tide::Result {
 
 pub async fn update(mut req: tide::Request<State>) -> tide::Result {
     let dino: Dino = req.body_json().await?;
-    let db_pool = req.state().db_pool.clone();
-    let id: Uuid = Uuid::parse_str(req.param("id")?).unwrap();
+    let dinos = req.state().dinos.clone();
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
+
+    // auth operation
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let current = match dinos.get(id).await? {
+        None => return Ok(Response::new(404)),
+        Some(current) => current,
+    };
+    if !can_modify(&current.user_id, &user_id) {
+        // 401
+        return Ok(Response::new(401));
+    }
+
+    // the owner of the body is ignored
+    let dino = Dino {
+        user_id: current.user_id,
+        ..dino
+    };
+    let row = dinos.update(id, dino, &actor(&req)).await?;
+
+    let res = match row {
+        None => Response::new(404),
+        Some(row) => {
+            let mut r = Response::new(200);
+            r.set_body(Body::from_json(&row)?);
+            r
+        }
+    };
+
+    Ok(res)
+}
+
+pub async fn patch(mut req: tide::Request<State>) -> tide::Result {
+    let content_type = req
+        .content_type()
+        .map(|mime| mime.essence().to_string())
+        .unwrap_or_default();
+    if content_type != MERGE_PATCH && content_type != JSON_PATCH {
+        return Ok(Response::new(415));
+    }
+
+    let patch: serde_json::Value = req.body_json().await?;
+    let dinos = req.state().dinos.clone();
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
 
     // auth operation
     let session = req.session();
-    let user_id: String = session.get("user_id").unwrap_or_else(|| "".to_string());
-    let row = handlers::dino::get(id, &db_pool).await?;
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let current = match dinos.get(id).await? {
+        None => return Ok(Response::new(404)),
+        Some(dino) => dino,
+    };
+    if !can_modify(&current.user_id, &user_id) {
+        // 401
+        return Ok(Response::new(401));
+    }
+
+    let mut doc = serde_json::to_value(&current)?;
+    if content_type == MERGE_PATCH {
+        json_patch::merge(&mut doc, &patch);
+    } else {
+        let patch: json_patch::Patch =
+            serde_json::from_value(patch).map_err(|e| Error::new(400, e))?;
+        json_patch::patch(&mut doc, &patch).map_err(|e| Error::new(409, e))?;
+    }
+
+    // the patched dino must still be a valid one
+    let dino: Dino = serde_json::from_value(doc).map_err(|e| Error::new(422, e))?;
+    if dino.id != current.id {
+        return Err(Error::from_str(422, "the id of a dino can't be changed"));
+    }
+    if dino.user_id != current.user_id {
+        return Err(Error::from_str(422, "the owner of a dino can't be changed"));
+    }
+
+    let changes = DinoChanges::between(&current, &dino);
+    let row = dinos.patch(id, changes, &actor(&req)).await?;
+
+    let res = match row {
+        None => Response::new(404),
+        Some(row) => {
+            let mut r = Response::new(200);
+            r.set_body(Body::from_json(&row)?);
+            r
+        }
+    };
+
+    Ok(res)
+}
+
+pub async fn delete(req: tide::Request<State>) -> tide::Result {
+    let dinos = req.state().dinos.clone();
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
+
+    // auth operation
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let row = dinos.get(id).await?;
     if let Some(dino) = row {
-        if dino.user_id.is_some() && dino.user_id.unwrap() != user_id {
+        if !can_modify(&dino.user_id, &user_id) {
             // 401
             return Ok(Response::new(401));
         }
     }
 
-    let row = handlers::dino::update(id, dino, &db_pool).await?;
+    let row = dinos.delete(id, &actor(&req)).await?;
+
+    let res = match row {
+        None => Response::new(404),
+        Some(_) => Response::new(204),
+    };
+
+    Ok(res)
+}
+
+pub async fn history(req: Request<State>) -> tide::Result {
+    let db_pool = req.state().db_pool.clone();
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
+    let rows = handlers::audit::history(id, &db_pool).await?;
+
+    if rows.is_empty() {
+        return Ok(Response::new(404));
+    }
+
+    let mut res = Response::new(200);
+    res.set_body(Body::from_json(&rows)?);
+    Ok(res)
+}
+
+pub async fn revisions(req: Request<State>) -> tide::Result {
+    let db_pool = req.state().db_pool.clone();
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
+    let rows = handlers::revision::list(id, &db_pool).await?;
+
+    if rows.is_empty() {
+        return Ok(Response::new(404));
+    }
+
+    let mut res = Response::new(200);
+    res.set_body(Body::from_json(&rows)?);
+    Ok(res)
+}
+
+// Restores an earlier revision of a dino, saved as a new one.
+pub async fn revert(req: Request<State>) -> tide::Result {
+    let db_pool = req.state().db_pool.clone();
+    let dinos = req.state().dinos.clone();
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
+    let rev: i32 = req.param("rev")?.parse().map_err(|e| Error::new(400, e))?;
+
+    // auth operation
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let current = match dinos.get(id).await? {
+        None => return Ok(Response::new(404)),
+        Some(current) => current,
+    };
+    if !can_modify(&current.user_id, &user_id) {
+        // 401
+        return Ok(Response::new(401));
+    }
+
+    let revision = match handlers::revision::get(id, rev, &db_pool).await? {
+        None => return Ok(Response::new(404)),
+        Some(revision) => revision,
+    };
+
+    // the owner isn't reverted
+    let dino = Dino {
+        user_id: current.user_id,
+        ..revision.dino()
+    };
+    let row = dinos.update(id, dino, &actor(&req)).await?;
 
     let res = match row {
         None => Response::new(404),
@@ -77,27 +366,281 @@ pub async fn update(mut req: tide::Request<State>) -> tide::Result {
     Ok(res)
 }
 
-pub async fn delete(req: tide::Request<State>) -> tide::Result {
-    let db_pool = req.state().db_pool.clone();
-    let id: Uuid = Uuid::parse_str(req.param("id")?).unwrap();
+pub async fn trash(req: Request<State>) -> tide::Result {
+    let dinos = req.state().dinos.clone();
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let rows = dinos.trash(&user_id).await?;
+
+    let mut res = Response::new(200);
+    res.set_body(Body::from_json(&rows)?);
+    Ok(res)
+}
+
+pub async fn restore(req: tide::Request<State>) -> tide::Result {
+    let dinos = req.state().dinos.clone();
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
 
     // auth operation
     let session = req.session();
-    let user_id: String = session.get("user_id").unwrap_or_else(|| "".to_string());
-    let row = handlers::dino::get(id, &db_pool).await?;
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let row = dinos.get_trashed(id).await?;
     if let Some(dino) = row {
-        if dino.user_id.is_some() && dino.user_id.unwrap() != user_id {
+        if !can_modify(&dino.user_id, &user_id) {
             // 401
             return Ok(Response::new(401));
         }
     }
 
-    let row = handlers::dino::delete(id, &db_pool).await?;
+    let row = dinos.restore(id, &actor(&req)).await?;
 
     let res = match row {
         None => Response::new(404),
-        Some(_) => Response::new(204),
+        Some(row) => {
+            let mut r = Response::new(200);
+            r.set_body(Body::from_json(&row)?);
+            r
+        }
+    };
+
+    Ok(res)
+}
+
+pub async fn batch(mut req: Request<State>) -> tide::Result {
+    let batch: BatchRequest = req.body_json().await?;
+    let max_size = req.state().batch_max_size;
+    if batch.operations.len() > max_size {
+        return Err(Error::from_str(
+            413,
+            format!("a batch can't have more than {} operations", max_size),
+        ));
+    }
+
+    let db_pool = req.state().db_pool.clone();
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let actor = actor(&req);
+
+    let mut results = Vec::with_capacity(batch.operations.len());
+    let committed = if batch.atomic {
+        let mut tx = db_pool.begin().await?;
+        let mut failed = false;
+        for operation in batch.operations {
+            if failed {
+                results.push(BatchResult::error(
+                    424,
+                    "not executed, a previous operation failed",
+                ));
+                continue;
+            }
+            let result = run_operation(operation, &user_id, &actor, &mut tx).await;
+            failed = result.error.is_some();
+            results.push(result);
+        }
+
+        if failed {
+            tx.rollback().await?;
+            for result in results.iter_mut().filter(|r| r.error.is_none()) {
+                *result = BatchResult::error(424, "rolled back, another operation failed");
+            }
+        } else {
+            tx.commit().await?;
+        }
+        !failed
+    } else {
+        let mut conn = db_pool.acquire().await?;
+        for operation in batch.operations {
+            results.push(run_operation(operation, &user_id, &actor, &mut conn).await);
+        }
+        true
     };
 
+    let mut res = Response::new(if committed { 200 } else { 409 });
+    res.set_body(Body::from_json(&BatchResponse { committed, results })?);
     Ok(res)
 }
+
+// runs one operation of a batch with the same rules as the single dino endpoints
+pub async fn run_operation(
+    operation: BatchOperation,
+    user_id: &str,
+    actor: &Actor,
+    conn: &mut PgConnection,
+) -> BatchResult {
+    let result = match operation {
+        BatchOperation::Create { dino } => {
+            let dino = Dino {
+                id: dino.id.unwrap_or_else(Uuid::new_v4),
+                name: dino.name,
+                weight: dino.weight,
+                diet: dino.diet,
+                user_id: Some(user_id.to_string()).filter(|id| !id.is_empty()),
+            };
+            handlers::dino::create(dino, actor, &mut *conn)
+                .await
+                .map(|row| BatchResult::ok(201, Some(row)))
+        }
+        BatchOperation::Update { id, dino } => match handlers::dino::get(id, &mut *conn).await {
+            Ok(Some(current)) if !can_modify(&current.user_id, user_id) => {
+                Ok(BatchResult::error(401, "the dino belongs to another user"))
+            }
+            Ok(Some(current)) => {
+                let dino = Dino {
+                    id,
+                    name: dino.name,
+                    weight: dino.weight,
+                    diet: dino.diet,
+                    user_id: current.user_id,
+                };
+                handlers::dino::update(id, dino, actor, &mut *conn)
+                    .await
+                    .map(|row| match row {
+                        None => BatchResult::error(404, "dino not found"),
+                        Some(row) => BatchResult::ok(200, Some(row)),
+                    })
+            }
+            Ok(None) => Ok(BatchResult::error(404, "dino not found")),
+            Err(e) => Err(e),
+        },
+        BatchOperation::Delete { id } => {
+            match handlers::dino::get(id, &mut *conn).await {
+                Ok(Some(current)) if !can_modify(&current.user_id, user_id) => {
+                    Ok(BatchResult::error(401, "the dino belongs to another user"))
+                }
+                Ok(Some(_)) => handlers::dino::delete(id, actor, &mut *conn)
+                    .await
+                    .map(|row| match row {
+                        None => BatchResult::error(404, "dino not found"),
+                        Some(_) => BatchResult::ok(204, None),
+                    }),
+                Ok(None) => Ok(BatchResult::error(404, "dino not found")),
+                Err(e) => Err(e),
+            }
+        }
+    };
+
+    result.unwrap_or_else(|e| BatchResult::error(e.status().into(), &e.to_string()))
+}
+
+pub async fn import(mut req: Request<State>) -> tide::Result {
+    let query: ImportQuery = req.query()?;
+    let format = match query.format.or_else(|| {
+        req.content_type()
+            .and_then(|mime| ImportFormat::from_content_type(mime.essence()))
+    }) {
+        None => return Ok(Response::new(415)),
+        Some(format) => format,
+    };
+
+    let db_pool = req.state().db_pool.clone();
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let actor = actor(&req);
+
+    let rows = DinoRows::new(req.take_body(), format, &query.mapping).await?;
+    let report = import_rows(
+        rows,
+        query.upsert,
+        query.dry_run,
+        &user_id,
+        &actor,
+        &db_pool,
+    )
+    .await?;
+
+    let mut res = Response::new(if report.errors.is_empty() { 200 } else { 422 });
+    res.set_body(Body::from_json(&report)?);
+    Ok(res)
+}
+
+// Imports every row in one transaction, committed when none of them failed and it's not a
+// dry run.
+pub async fn import_rows<R: async_std::io::BufRead + Unpin>(
+    mut rows: DinoRows<R>,
+    upsert: bool,
+    dry_run: bool,
+    user_id: &str,
+    actor: &Actor,
+    db_pool: &PgPool,
+) -> tide::Result<ImportReport> {
+    let mut report = ImportReport {
+        dry_run,
+        ..ImportReport::default()
+    };
+
+    // every row runs in its own savepoint, so a failing one doesn't abort the others
+    let mut tx = db_pool.begin().await?;
+    while let Some((row, dino)) = rows.next().await? {
+        let result = match dino {
+            Ok(dino) => {
+                let mut savepoint = tx.begin().await?;
+                let result = import_row(dino, upsert, user_id, actor, &mut savepoint).await;
+                match result {
+                    Ok(_) => savepoint.commit().await?,
+                    Err(_) => savepoint.rollback().await?,
+                }
+                result
+            }
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(true) => report.created += 1,
+            Ok(false) => report.updated += 1,
+            Err(error) => report.errors.push(ImportError { row, error }),
+        }
+    }
+
+    report.committed = report.errors.is_empty() && !dry_run;
+    if report.committed {
+        tx.commit().await?;
+    } else {
+        tx.rollback().await?;
+    }
+
+    Ok(report)
+}
+
+// Creates the dino of an imported row, or updates it when `upsert` is set and it already
+// exists. Returns whether it was created.
+async fn import_row(
+    dino: NewDino,
+    upsert: bool,
+    user_id: &str,
+    actor: &Actor,
+    conn: &mut PgConnection,
+) -> Result<bool, String> {
+    let id = dino.id.unwrap_or_else(Uuid::new_v4);
+    let mut dino = Dino {
+        id,
+        name: dino.name,
+        weight: dino.weight,
+        diet: dino.diet,
+        user_id: Some(user_id.to_string()).filter(|id| !id.is_empty()),
+    };
+
+    let current = if upsert {
+        handlers::dino::get(id, &mut *conn)
+            .await
+            .map_err(|e| e.to_string())?
+    } else {
+        None
+    };
+
+    match current {
+        Some(current) => {
+            if !can_modify(&current.user_id, user_id) {
+                return Err("the dino belongs to another user".to_string());
+            }
+            dino.user_id = current.user_id;
+            handlers::dino::update(id, dino, actor, &mut *conn)
+                .await
+                .map(|_| false)
+                .map_err(|e| e.to_string())
+        }
+        None => handlers::dino::create(dino, actor, &mut *conn)
+            .await
+            .map(|_| true)
+            .map_err(|e| e.to_string()),
+    }
+}
@@ -0,0 +1,33 @@
+use super::*;
+
+use tide::{Body, Request, Response};
+
+use crate::{api, openapi};
+
+pub async fn openapi(_req: Request<State>) -> tide::Result {
+    let routes: Vec<_> = api::v1::routes()
+        .into_iter()
+        .map(|(method, path, _)| (method, path))
+        .collect();
+
+    let mut res = Response::new(200);
+    res.set_body(Body::from_json(&openapi::document(
+        api::v1::PREFIX,
+        &routes,
+    ))?);
+    Ok(res)
+}
+
+pub async fn index(req: Request<State>) -> tide::Result {
+    let tera = req.state().tera.clone();
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+
+    tera.render_response(
+        "docs.html",
+        &context! {
+            "title" => String::from("API docs"),
+            "user_id" => user_id,
+        },
+    )
+}
@@ -0,0 +1,47 @@
+use super::*;
+
+use subtle::ConstantTimeEq;
+use tide::sessions::Session;
+use tide::{http, Request, Response};
+
+use crate::handlers;
+use crate::handlers::user::ADMIN;
+use crate::metrics;
+
+// whether the logged in user is an enabled admin, the role is read on every request so a
+// demoted user loses access right away
+async fn is_admin(req: &Request<State>) -> tide::Result<bool> {
+    // the admin port has no sessions
+    let user_id: Option<String> = req
+        .ext::<Session>()
+        .and_then(|session| session.get("user_id"));
+    let user = match user_id {
+        None => return Ok(false),
+        Some(user_id) => handlers::user::get(&user_id, &req.state().db_pool).await?,
+    };
+    Ok(user.is_some_and(|user| user.role == ADMIN && !user.disabled))
+}
+
+pub async fn show(req: Request<State>) -> tide::Result {
+    // when a token is configured the scraper must send it as a bearer token, compared in
+    // constant time so the response time doesn't tell how much of it matched; admins can
+    // read them from their browser
+    if let Some(token) = &req.state().metrics_token {
+        let expected = format!("Bearer {}", token);
+        let authorized = match req.header(http::headers::AUTHORIZATION) {
+            Some(value) => bool::from(value.as_str().as_bytes().ct_eq(expected.as_bytes())),
+            None => false,
+        };
+        if !authorized && !is_admin(&req).await? {
+            return Ok(Response::new(401));
+        }
+    }
+
+    let state = req.state();
+    metrics::sample(&state.db_pool).await;
+
+    let mut res = Response::new(200);
+    res.set_content_type("text/plain; version=0.0.4");
+    res.set_body(metrics::render()?);
+    Ok(res)
+}
@@ -2,4 +2,8 @@ use super::*;
 
 pub mod auth;
 pub mod dino;
+pub mod docs;
+pub mod metrics;
+pub mod socket;
 pub mod views;
+pub mod webhook;
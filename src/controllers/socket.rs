@@ -0,0 +1,220 @@
+use super::*;
+
+use async_std::stream::{self, StreamExt};
+use async_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use async_tungstenite::tungstenite::protocol::CloseFrame;
+use async_tungstenite::tungstenite::{self, Message};
+use futures_util::SinkExt;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tide::Request;
+
+use crate::controllers::dino::{actor, run_operation};
+use crate::events::{DinoEvent, DinoEventKind};
+use crate::websocket::WebSocket;
+use crate::{BatchOperation, BatchResult};
+
+// how often the server pings an idle client
+static HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+// a client that hasn't sent anything, pongs included, for that long is disconnected
+static CLIENT_TIMEOUT: Duration = Duration::from_secs(75);
+
+// the subscriptions a client can hold at once
+static MAX_SUBSCRIPTIONS: usize = 32;
+
+// The dinos a subscription is interested in, every dino when empty.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Filter {
+    pub id: Option<Uuid>,
+    pub diet: Option<String>,
+    pub owner: Option<String>,
+}
+
+impl Filter {
+    fn matches(&self, dino: &Dino) -> bool {
+        self.id.is_none_or(|id| id == dino.id)
+            && self.diet.as_ref().is_none_or(|diet| diet == &dino.diet)
+            && self
+                .owner
+                .as_ref()
+                .is_none_or(|owner| Some(owner) == dino.user_id.as_ref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    Subscribe {
+        id: String,
+        #[serde(default)]
+        filter: Filter,
+    },
+    Unsubscribe {
+        id: String,
+    },
+    // a create, update or delete with the same body as a batch operation
+    Command {
+        #[serde(rename = "ref", default)]
+        reference: Option<String>,
+        #[serde(flatten)]
+        operation: BatchOperation,
+    },
+    Get {
+        #[serde(rename = "ref", default)]
+        reference: Option<String>,
+        id: Uuid,
+    },
+    Ping,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage<'a> {
+    Subscribed {
+        id: String,
+    },
+    Unsubscribed {
+        id: String,
+    },
+    Event {
+        subscription: &'a str,
+        kind: DinoEventKind,
+        dino: &'a Dino,
+    },
+    Result {
+        #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+        reference: Option<String>,
+        #[serde(flatten)]
+        result: BatchResult,
+    },
+    Pong,
+    Error {
+        error: String,
+    },
+}
+
+impl ServerMessage<'_> {
+    fn to_message(&self) -> tide::Result<Message> {
+        Ok(Message::Text(serde_json::to_string(self)?))
+    }
+}
+
+enum Input {
+    Message(Option<Result<Message, tungstenite::Error>>),
+    Event(Option<DinoEvent>),
+    Heartbeat,
+}
+
+// Streams the changes matching the client's subscriptions and runs its commands.
+pub async fn dinos(req: Request<State>, socket: WebSocket) -> tide::Result<()> {
+    let db_pool = req.state().db_pool.clone();
+    let dinos = req.state().dinos.clone();
+    let user_id: String = req.session().get("user_id").unwrap_or_default();
+    let actor = actor(&req);
+
+    // each source ends with a `None` so the loop can tell which one is gone
+    let (mut sink, incoming) = futures_util::StreamExt::split(socket);
+    let incoming = incoming
+        .map(|message| Input::Message(Some(message)))
+        .chain(stream::once(Input::Message(None)));
+    let events = req
+        .state()
+        .events
+        .subscribe()
+        .map(|event| Input::Event(Some(event)))
+        .chain(stream::once(Input::Event(None)));
+    let heartbeat = stream::interval(HEARTBEAT_INTERVAL).map(|_| Input::Heartbeat);
+    let mut inputs = incoming.merge(events).merge(heartbeat);
+
+    let mut subscriptions: HashMap<String, Filter> = HashMap::new();
+    let mut last_seen = Instant::now();
+
+    while let Some(input) = inputs.next().await {
+        let message = match input {
+            Input::Message(None) | Input::Message(Some(Err(_))) => break,
+            Input::Message(Some(Ok(message))) => {
+                last_seen = Instant::now();
+                message
+            }
+            // the replies are sent one at a time, a client that doesn't read them fills up its
+            // event buffer and is told to come back
+            Input::Event(None) => {
+                let close = CloseFrame {
+                    code: CloseCode::Again,
+                    reason: "too slow, events were dropped".into(),
+                };
+                sink.send(Message::Close(Some(close))).await?;
+                break;
+            }
+            Input::Event(Some(event)) => {
+                for (id, filter) in &subscriptions {
+                    if filter.matches(&event.dino) {
+                        let message = ServerMessage::Event {
+                            subscription: id,
+                            kind: event.kind,
+                            dino: &event.dino,
+                        };
+                        sink.send(message.to_message()?).await?;
+                    }
+                }
+                continue;
+            }
+            Input::Heartbeat => {
+                if last_seen.elapsed() > CLIENT_TIMEOUT {
+                    sink.send(Message::Close(None)).await?;
+                    break;
+                }
+                sink.send(Message::Ping(vec![])).await?;
+                continue;
+            }
+        };
+
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            // pings are answered by tungstenite, pongs only keep the client alive
+            _ => continue,
+        };
+        let reply = match serde_json::from_str(&text) {
+            Err(e) => ServerMessage::Error {
+                error: e.to_string(),
+            },
+            Ok(ClientMessage::Ping) => ServerMessage::Pong,
+            Ok(ClientMessage::Subscribe { id, .. })
+                if subscriptions.len() >= MAX_SUBSCRIPTIONS && !subscriptions.contains_key(&id) =>
+            {
+                ServerMessage::Error {
+                    error: format!("at most {} subscriptions", MAX_SUBSCRIPTIONS),
+                }
+            }
+            Ok(ClientMessage::Subscribe { id, filter }) => {
+                subscriptions.insert(id.clone(), filter);
+                ServerMessage::Subscribed { id }
+            }
+            Ok(ClientMessage::Unsubscribe { id }) => {
+                subscriptions.remove(&id);
+                ServerMessage::Unsubscribed { id }
+            }
+            Ok(ClientMessage::Command {
+                reference,
+                operation,
+            }) => {
+                let mut conn = db_pool.acquire().await?;
+                let result = run_operation(operation, &user_id, &actor, &mut conn).await;
+                ServerMessage::Result { reference, result }
+            }
+            Ok(ClientMessage::Get { reference, id }) => {
+                let result = match dinos.get(id).await {
+                    Ok(Some(dino)) => BatchResult::ok(200, Some(dino)),
+                    Ok(None) => BatchResult::error(404, "dino not found"),
+                    Err(e) => BatchResult::error(e.status().into(), &e.to_string()),
+                };
+                ServerMessage::Result { reference, result }
+            }
+        };
+        sink.send(reply.to_message()?).await?;
+    }
+
+    Ok(())
+}
@@ -3,15 +3,11 @@ use tide::{Request, Response};
 
 pub async fn index(req: Request<State>) -> tide::Result {
     let tera = req.state().tera.clone();
-    let db_pool = req.state().db_pool.clone();
-    let rows = handlers::dino::list(&db_pool).await?;
+    let rows = req.state().dinos.list().await?;
 
     let session = req.session();
-    println!("{:?}", session);
-    let user_id: String = session.get("user_id").unwrap_or_else(|| "".to_string());
-    let user_name: String = session.get("user_name").unwrap_or_else(|| "".to_string());
-
-    println!(" data {}, {}", user_id, user_name);
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let user_name: String = session.get("user_name").unwrap_or_default();
 
     tera.render_response(
         "index.html",
@@ -27,7 +23,7 @@ pub async fn index(req: Request<State>) -> tide::Result {
 pub async fn new(req: Request<State>) -> tide::Result {
     let tera = req.state().tera.clone();
     let session = req.session();
-    let user_id: String = session.get("user_id").unwrap_or_else(|| "".to_string());
+    let user_id: String = session.get("user_id").unwrap_or_default();
 
     tera.render_response(
         "form.html",
@@ -38,24 +34,58 @@ pub async fn new(req: Request<State>) -> tide::Result {
     )
 }
 
+pub async fn import(req: Request<State>) -> tide::Result {
+    let tera = req.state().tera.clone();
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+
+    tera.render_response(
+        "import.html",
+        &context! {
+            "title" => String::from("Import dinos"),
+            "user_id" => user_id,
+        },
+    )
+}
+
+pub async fn trash(req: Request<State>) -> tide::Result {
+    let tera = req.state().tera.clone();
+    let session = req.session();
+    let user_id: String = session.get("user_id").unwrap_or_default();
+    let rows = req.state().dinos.trash(&user_id).await?;
+
+    tera.render_response(
+        "trash.html",
+        &context! {
+            "title" => String::from("Trash"),
+            "dinos" => rows,
+            "user_id" => user_id,
+        },
+    )
+}
+
 pub async fn edit(req: Request<State>) -> tide::Result {
     let tera = req.state().tera.clone();
     let session = req.session();
-    let user_id: String = session.get("user_id").unwrap_or_else(|| "".to_string());
+    let user_id: String = session.get("user_id").unwrap_or_default();
 
     let db_pool = req.state().db_pool.clone();
-    let id: Uuid = Uuid::parse_str(req.param("id")?).unwrap();
-    let row = handlers::dino::get(id, &db_pool).await?;
+    let id: Uuid = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
+    let row = req.state().dinos.get(id).await?;
 
     let res = match row {
         None => Response::new(404),
         Some(row) => {
+            let history = handlers::audit::history(id, &db_pool).await?;
+            let revisions = handlers::revision::list(id, &db_pool).await?;
             let mut r = Response::new(200);
             let b = tera.render_body(
                 "form.html",
                 &context! {
                     "title" => String::from("Edit dino"),
                     "dino" => row,
+                    "history" => history,
+                    "revisions" => revisions,
                     "user_id" => user_id,
                 },
             )?;
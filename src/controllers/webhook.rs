@@ -0,0 +1,118 @@
+use super::*;
+
+use tide::http::Url;
+use tide::{Body, Request, Response};
+
+use crate::events::DinoEventKind;
+use crate::handlers;
+use crate::handlers::webhook::Webhook;
+use crate::webhooks;
+
+// The body of a webhook registration, every kind of event by default.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct NewWebhook {
+    url: String,
+    #[serde(default = "NewWebhook::default_events")]
+    events: Vec<DinoEventKind>,
+    #[serde(default)]
+    diet: Option<String>,
+    // generated when missing
+    #[serde(default)]
+    secret: Option<String>,
+}
+
+impl NewWebhook {
+    fn default_events() -> Vec<DinoEventKind> {
+        vec![
+            DinoEventKind::Created,
+            DinoEventKind::Updated,
+            DinoEventKind::Deleted,
+        ]
+    }
+}
+
+// the logged in user, the webhooks and their secrets are never shared with anonymous clients
+fn user_id(req: &Request<State>) -> tide::Result<String> {
+    req.session()
+        .get("user_id")
+        .ok_or_else(|| Error::from_str(401, "webhooks require a logged in user"))
+}
+
+// the webhook `id` if it belongs to the logged in user
+async fn owned(req: &Request<State>) -> tide::Result<Option<Webhook>> {
+    let user_id = user_id(req)?;
+    let id = Uuid::parse_str(req.param("id")?).map_err(|e| Error::new(400, e))?;
+    let webhook = handlers::webhook::get(id, &req.state().db_pool).await?;
+    Ok(webhook.filter(|webhook| webhook.user_id == user_id))
+}
+
+pub async fn create(mut req: Request<State>) -> tide::Result {
+    let user_id = user_id(&req)?;
+    let new_webhook: NewWebhook = req.body_json().await?;
+    let url = match Url::parse(&new_webhook.url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+        _ => {
+            return Err(Error::from_str(
+                422,
+                "the url must be an absolute http(s) url",
+            ))
+        }
+    };
+    webhooks::check_target(&url, req.state().webhook_allow_private_targets)
+        .await
+        .map_err(|e| Error::from_str(422, e))?;
+    if new_webhook.events.is_empty() {
+        return Err(Error::from_str(422, "a webhook needs at least one event"));
+    }
+
+    let mut events: Vec<String> = vec![];
+    for kind in new_webhook.events {
+        if !events.iter().any(|event| event == kind.as_str()) {
+            events.push(kind.as_str().to_string());
+        }
+    }
+    let webhook = Webhook {
+        id: Uuid::new_v4(),
+        user_id,
+        url: new_webhook.url,
+        secret: new_webhook
+            .secret
+            .unwrap_or_else(|| Uuid::new_v4().to_simple().to_string()),
+        events,
+        diet: new_webhook.diet,
+        created_at: chrono::Utc::now(),
+    };
+
+    let row = handlers::webhook::create(&webhook, &req.state().db_pool).await?;
+    let mut res = Response::new(201);
+    res.set_body(Body::from_json(&row)?);
+    Ok(res)
+}
+
+pub async fn list(req: Request<State>) -> tide::Result {
+    let user_id = user_id(&req)?;
+    let rows = handlers::webhook::list(&user_id, &req.state().db_pool).await?;
+    let mut res = Response::new(200);
+    res.set_body(Body::from_json(&rows)?);
+    Ok(res)
+}
+
+pub async fn delete(req: Request<State>) -> tide::Result {
+    let webhook = match owned(&req).await? {
+        None => return Ok(Response::new(404)),
+        Some(webhook) => webhook,
+    };
+    handlers::webhook::delete(webhook.id, &req.state().db_pool).await?;
+    Ok(Response::new(204))
+}
+
+pub async fn deliveries(req: Request<State>) -> tide::Result {
+    let webhook = match owned(&req).await? {
+        None => return Ok(Response::new(404)),
+        Some(webhook) => webhook,
+    };
+    let rows = handlers::webhook::deliveries(webhook.id, &req.state().db_pool).await?;
+    let mut res = Response::new(200);
+    res.set_body(Body::from_json(&rows)?);
+    Ok(res)
+}
@@ -0,0 +1,108 @@
+use super::*;
+
+use async_std::channel::{self, Receiver, Sender, TrySendError};
+use sqlx::postgres::PgListener;
+use sqlx::{query, PgExecutor};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// the Postgres channel the dino changes are published on
+pub static CHANNEL: &str = "dino_changes";
+
+// the events buffered for a subscriber before it's considered too slow and dropped
+static SUBSCRIBER_BUFFER: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DinoEventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl DinoEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DinoEventKind::Created => "created",
+            DinoEventKind::Updated => "updated",
+            DinoEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DinoEvent {
+    pub kind: DinoEventKind,
+    pub dino: Dino,
+}
+
+// Publishes a change to every server instance, once the transaction of `executor` commits.
+pub async fn notify<'e, E: PgExecutor<'e>>(
+    kind: DinoEventKind,
+    dino: &Dino,
+    executor: E,
+) -> tide::Result<()> {
+    let payload = serde_json::to_string(&DinoEvent {
+        kind,
+        dino: dino.clone(),
+    })?;
+    query!("SELECT pg_notify($1, $2)", CHANNEL, payload)
+        .execute(executor)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// Fans the changes received by this instance out to its subscribers.
+#[derive(Debug, Clone, Default)]
+pub struct Broadcaster {
+    subscribers: Arc<Mutex<Vec<Sender<DinoEvent>>>>,
+}
+
+impl Broadcaster {
+    pub fn subscribe(&self) -> Receiver<DinoEvent> {
+        let (sender, receiver) = channel::bounded(SUBSCRIBER_BUFFER);
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    // A subscriber that doesn't keep up is dropped instead of slowing down the others,
+    // its stream ends and the client has to reconnect.
+    pub fn publish(&self, event: DinoEvent) {
+        self.subscribers.lock().unwrap().retain(|subscriber| {
+            match subscriber.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) | Err(TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+}
+
+// Listens to the changes published by every instance, reconnecting when the connection is lost,
+// until the pool is closed.
+pub async fn listen(db_pool: PgPool, broadcaster: Broadcaster) {
+    while !db_pool.is_closed() {
+        let forwarded = db_pool
+            .close_event()
+            .do_until(forward(&db_pool, &broadcaster))
+            .await;
+        if let Ok(Err(e)) = forwarded {
+            tide::log::error!("dino events listener failed", { error: e.to_string() });
+        }
+        async_std::task::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn forward(db_pool: &PgPool, broadcaster: &Broadcaster) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect_with(db_pool).await?;
+    listener.listen(CHANNEL).await?;
+
+    loop {
+        let notification = listener.recv().await?;
+        match serde_json::from_str(notification.payload()) {
+            Ok(event) => broadcaster.publish(event),
+            Err(e) => tide::log::warn!("invalid dino event", { error: e.to_string() }),
+        }
+    }
+}
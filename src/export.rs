@@ -0,0 +1,151 @@
+use super::*;
+
+use async_std::channel::Receiver;
+use async_std::io::{self, BufRead, Read};
+use async_std::stream::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tide::http::Mime;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    #[default]
+    Json,
+}
+
+impl ExportFormat {
+    pub fn mime(&self) -> Mime {
+        match self {
+            ExportFormat::Csv => Mime::from("text/csv"),
+            ExportFormat::Ndjson => Mime::from("application/x-ndjson"),
+            ExportFormat::Json => tide::http::mime::JSON,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+// Encodes dinos one at a time, so an export never holds more than a row in memory.
+pub struct Encoder {
+    format: ExportFormat,
+    rows: usize,
+}
+
+impl Encoder {
+    pub fn new(format: ExportFormat) -> Self {
+        Encoder { format, rows: 0 }
+    }
+
+    pub fn header(&self) -> tide::Result<Vec<u8>> {
+        match self.format {
+            ExportFormat::Csv => csv_record(["id", "name", "weight", "diet", "user_id"]),
+            ExportFormat::Ndjson => Ok(vec![]),
+            ExportFormat::Json => Ok(b"[".to_vec()),
+        }
+    }
+
+    pub fn row(&mut self, dino: &Dino) -> tide::Result<Vec<u8>> {
+        self.rows += 1;
+        match self.format {
+            ExportFormat::Csv => csv_record(dino),
+            ExportFormat::Ndjson => {
+                let mut line = serde_json::to_vec(dino)?;
+                line.push(b'\n');
+                Ok(line)
+            }
+            ExportFormat::Json => {
+                let mut item = if self.rows > 1 { b",".to_vec() } else { vec![] };
+                item.extend(serde_json::to_vec(dino)?);
+                Ok(item)
+            }
+        }
+    }
+
+    pub fn footer(&self) -> Vec<u8> {
+        match self.format {
+            ExportFormat::Json => b"]\n".to_vec(),
+            _ => vec![],
+        }
+    }
+}
+
+fn csv_record<T: Serialize>(record: T) -> tide::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.serialize(record)?;
+    writer
+        .into_inner()
+        .map_err(|e| Error::from_str(500, e.to_string()))
+}
+
+// A response body fed by the chunks sent on a channel, it ends when every sender is dropped.
+pub struct ChunkReader {
+    chunks: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ChunkReader {
+    pub fn new(chunks: Receiver<Vec<u8>>) -> Self {
+        ChunkReader {
+            chunks,
+            chunk: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl BufRead for ChunkReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        while this.pos >= this.chunk.len() {
+            match Pin::new(&mut this.chunks).poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => break,
+                Poll::Ready(Some(chunk)) => {
+                    this.chunk = chunk;
+                    this.pos = 0;
+                }
+            }
+        }
+        Poll::Ready(Ok(&this.chunk[this.pos..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().pos += amt;
+    }
+}
+
+impl Read for ChunkReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let available = match self.as_mut().poll_fill_buf(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Ready(Ok(available)) => available,
+        };
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Poll::Ready(Ok(len))
+    }
+}
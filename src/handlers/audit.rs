@@ -0,0 +1,110 @@
+use super::*;
+use crate::metrics::DB_QUERY_DURATION;
+use crate::telemetry::{query_span, POSTGRESQL};
+use crate::Dino;
+use serde_json::{Map, Value};
+use sqlx::{query, query_as, PgExecutor};
+
+// Who made a change, and in which request.
+#[derive(Debug, Clone, Default)]
+pub struct Actor {
+    pub user_id: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct AuditRecord {
+    pub id: Uuid,
+    pub dino_id: Uuid,
+    pub action: String,
+    pub actor_id: Option<String>,
+    pub request_id: Option<String>,
+    pub changed_at: chrono::DateTime<chrono::Utc>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    // the changed fields, `{"weight": {"from": 500, "to": 600}}`
+    pub diff: Value,
+}
+
+// Records a change of a dino, `before` is `None` when it's created and `after` when it's deleted.
+pub async fn record<'e, E: PgExecutor<'e>>(
+    action: &str,
+    before: Option<&Dino>,
+    after: Option<&Dino>,
+    actor: &Actor,
+    executor: E,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["audit_record"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "audit_record", "INSERT INTO dino_audit");
+
+    let dino_id = match before.or(after) {
+        None => return Ok(()),
+        Some(dino) => dino.id,
+    };
+    let before = before.map(serde_json::to_value).transpose()?;
+    let after = after.map(serde_json::to_value).transpose()?;
+    let diff = diff(before.as_ref(), after.as_ref());
+
+    query!(
+        r#"
+        INSERT INTO dino_audit (id, dino_id, action, actor_id, request_id, before, after, diff) VALUES
+        ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        dino_id,
+        action,
+        actor.user_id,
+        actor.request_id,
+        before,
+        after,
+        diff
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// The changes of a dino, the oldest first.
+pub async fn history<'e, E: PgExecutor<'e>>(
+    dino_id: Uuid,
+    executor: E,
+) -> tide::Result<Vec<AuditRecord>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["audit_history"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "audit_history", "SELECT FROM dino_audit");
+    let rows = query_as!(
+        AuditRecord,
+        r#"
+        SELECT id, dino_id, action, actor_id, request_id, changed_at, before, after, diff
+        from dino_audit
+        WHERE dino_id = $1
+        ORDER BY changed_at
+        "#,
+        dino_id
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(rows)
+}
+
+fn diff(before: Option<&Value>, after: Option<&Value>) -> Value {
+    let empty = Map::new();
+    let before = before.and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.and_then(Value::as_object).unwrap_or(&empty);
+
+    let mut diff = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let (from, to) = (before.get(key), after.get(key));
+        if from != to && !diff.contains_key(key) {
+            diff.insert(key.clone(), serde_json::json!({ "from": from, "to": to }));
+        }
+    }
+    Value::Object(diff)
+}
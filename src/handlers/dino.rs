@@ -1,8 +1,65 @@
 use super::*;
-use crate::Dino;
-use sqlx::{query, query_as, PgPool};
+use crate::events::{self, DinoEventKind};
+use crate::handlers::audit::{self, Actor};
+use crate::handlers::{revision, webhook};
+use crate::metrics::DB_QUERY_DURATION;
+use crate::telemetry::{query_span, POSTGRESQL};
+use crate::{Dino, DinoChanges, TrashedDino};
+use async_std::stream::{Stream, StreamExt};
+use sqlx::{query, query_as, Acquire, PgExecutor, Postgres};
 
-pub async fn create(dino: Dino, db_pool: &PgPool) -> tide::Result<Dino> {
+// the longest text field of a dino, the events of a dino must fit in a NOTIFY payload
+static MAX_TEXT_LEN: usize = 255;
+
+// Checks the text fields a write stores, every write of a dino goes through `create`, `update` or
+// `patch`. Postgres can't store the NUL character in a text column.
+pub fn validate_text(fields: &[Option<&str>]) -> tide::Result<()> {
+    for field in fields.iter().flatten() {
+        if field.contains('\0') {
+            return Err(Error::from_str(
+                422,
+                "the fields of a dino can't contain NUL characters",
+            ));
+        }
+        if field.chars().count() > MAX_TEXT_LEN {
+            return Err(Error::from_str(
+                422,
+                format!(
+                    "the fields of a dino can't be longer than {} characters",
+                    MAX_TEXT_LEN
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn validate(dino: &Dino) -> tide::Result<()> {
+    validate_text(&[Some(&dino.name), Some(&dino.diet), dino.user_id.as_deref()])
+}
+
+pub fn validate_changes(changes: &DinoChanges) -> tide::Result<()> {
+    validate_text(&[
+        changes.name.as_deref(),
+        changes.diet.as_deref(),
+        changes
+            .user_id
+            .as_ref()
+            .and_then(|user_id| user_id.as_deref()),
+    ])
+}
+
+pub async fn create<'c, A: Acquire<'c, Database = Postgres>>(
+    dino: Dino,
+    actor: &Actor,
+    conn: A,
+) -> tide::Result<Dino> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_create"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_create", "INSERT INTO dinos");
+    validate(&dino)?;
+    let mut tx = conn.begin().await?;
     let row: Dino = query_as!(
         Dino,
         r#"
@@ -15,68 +72,239 @@ pub async fn create(dino: Dino, db_pool: &PgPool) -> tide::Result<Dino> {
         dino.diet,
         dino.user_id
     )
-    .fetch_one(db_pool)
+    .fetch_one(&mut tx)
     .await
     .map_err(|e| Error::new(409, e))?;
 
+    audit::record("create", None, Some(&row), actor, &mut tx).await?;
+    revision::record(None, &row, actor, &mut tx).await?;
+    events::notify(DinoEventKind::Created, &row, &mut tx).await?;
+    webhook::enqueue(DinoEventKind::Created, &row, &mut tx).await?;
+    tx.commit().await?;
+
     Ok(row)
 }
-pub async fn list(db_pool: &PgPool) -> tide::Result<Vec<Dino>> {
+pub async fn list<'e, E: PgExecutor<'e>>(executor: E) -> tide::Result<Vec<Dino>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_list"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_list", "SELECT FROM dinos");
     let rows = query_as!(
         Dino,
         r#"
         SELECT id, name, weight, diet, user_id from dinos
+        WHERE deleted_at IS NULL
         "#
     )
-    .fetch_all(db_pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| Error::new(409, e))?;
 
     Ok(rows)
 }
 
-pub async fn get(id: Uuid, db_pool: &PgPool) -> tide::Result<Option<Dino>> {
+// Like `list`, but the rows are fetched one at a time as the stream is polled.
+pub fn stream<'e, E: 'e + PgExecutor<'e>>(
+    executor: E,
+) -> impl Stream<Item = tide::Result<Dino>> + 'e {
+    let timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_stream"])
+        .start_timer();
+    let span = query_span(POSTGRESQL, "dino_stream", "SELECT FROM dinos");
+    query_as!(
+        Dino,
+        r#"
+        SELECT id, name, weight, diet, user_id from dinos
+        WHERE deleted_at IS NULL
+        "#
+    )
+    .fetch(executor)
+    .map(move |row| {
+        // the timer and the span end when the stream is dropped
+        let _ = (&timer, &span);
+        row.map_err(|e| Error::new(409, e))
+    })
+}
+
+pub async fn get<'e, E: PgExecutor<'e>>(id: Uuid, executor: E) -> tide::Result<Option<Dino>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_get"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_get", "SELECT FROM dinos");
     let row = query_as!(
         Dino,
         r#"
         SELECT  id, name, weight, diet, user_id from dinos
-        WHERE id = $1
+        WHERE id = $1 AND deleted_at IS NULL
         "#,
         id
     )
-    .fetch_optional(db_pool)
+    .fetch_optional(executor)
     .await
     .map_err(|e| Error::new(409, e))?;
 
     Ok(row)
 }
-pub async fn delete(id: Uuid, db_pool: &PgPool) -> tide::Result<Option<()>> {
-    let row = query!(
+// Moves a dino to the trash, it can be restored until it's purged.
+pub async fn delete<'c, A: Acquire<'c, Database = Postgres>>(
+    id: Uuid,
+    actor: &Actor,
+    conn: A,
+) -> tide::Result<Option<()>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_delete"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_delete", "UPDATE dinos");
+    let mut tx = conn.begin().await?;
+    let row = query_as!(
+        Dino,
         r#"
-        delete from dinos
-        WHERE id = $1
-        returning id
+        UPDATE dinos SET deleted_at = now()
+        WHERE id = $1 AND deleted_at IS NULL
+        returning id, name, weight, diet, user_id
         "#,
         id
     )
-    .fetch_optional(db_pool)
+    .fetch_optional(&mut tx)
     .await
     .map_err(|e| Error::new(409, e))?;
 
     let r = match row {
         None => None,
-        Some(_) => Some(()),
+        Some(row) => {
+            audit::record("delete", Some(&row), None, actor, &mut tx).await?;
+            events::notify(DinoEventKind::Deleted, &row, &mut tx).await?;
+            webhook::enqueue(DinoEventKind::Deleted, &row, &mut tx).await?;
+            Some(())
+        }
     };
+    tx.commit().await?;
 
     Ok(r)
 }
 
-pub async fn update(id: Uuid, dino: Dino, db_pool: &PgPool) -> tide::Result<Option<Dino>> {
+// The dinos in the trash that `user_id` can restore, the most recently deleted first.
+pub async fn trash<'e, E: PgExecutor<'e>>(
+    user_id: &str,
+    executor: E,
+) -> tide::Result<Vec<TrashedDino>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_trash"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_trash", "SELECT FROM dinos");
+    let rows = query_as!(
+        TrashedDino,
+        r#"
+        SELECT id, name, weight, diet, user_id, deleted_at as "deleted_at!" from dinos
+        WHERE deleted_at IS NOT NULL AND (user_id = $1 OR user_id IS NULL)
+        ORDER BY deleted_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(rows)
+}
+
+pub async fn get_trashed<'e, E: PgExecutor<'e>>(
+    id: Uuid,
+    executor: E,
+) -> tide::Result<Option<TrashedDino>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_get_trashed"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_get_trashed", "SELECT FROM dinos");
+    let row = query_as!(
+        TrashedDino,
+        r#"
+        SELECT id, name, weight, diet, user_id, deleted_at as "deleted_at!" from dinos
+        WHERE id = $1 AND deleted_at IS NOT NULL
+        "#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+pub async fn restore<'c, A: Acquire<'c, Database = Postgres>>(
+    id: Uuid,
+    actor: &Actor,
+    conn: A,
+) -> tide::Result<Option<Dino>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_restore"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_restore", "UPDATE dinos");
+    let mut tx = conn.begin().await?;
+    let row = query_as!(
+        Dino,
+        r#"
+        UPDATE dinos SET deleted_at = NULL
+        WHERE id = $1 AND deleted_at IS NOT NULL
+        returning id, name, weight, diet, user_id
+        "#,
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    if let Some(row) = &row {
+        audit::record("restore", None, Some(row), actor, &mut tx).await?;
+        events::notify(DinoEventKind::Created, row, &mut tx).await?;
+        webhook::enqueue(DinoEventKind::Created, row, &mut tx).await?;
+    }
+    tx.commit().await?;
+
+    Ok(row)
+}
+
+// Deletes for good the dinos in the trash for longer than `retention_secs`.
+pub async fn purge<'e, E: PgExecutor<'e>>(retention_secs: i64, executor: E) -> tide::Result<u64> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_purge"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_purge", "DELETE FROM dinos");
+    let result = query!(
+        r#"
+        DELETE FROM dinos
+        WHERE deleted_at < now() - $1 * interval '1 second'
+        "#,
+        retention_secs as f64
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn update<'c, A: Acquire<'c, Database = Postgres>>(
+    id: Uuid,
+    dino: Dino,
+    actor: &Actor,
+    conn: A,
+) -> tide::Result<Option<Dino>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_update"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_update", "UPDATE dinos");
+    validate(&dino)?;
+    let mut tx = conn.begin().await?;
+    let before = match lock(id, &mut tx).await? {
+        None => return Ok(None),
+        Some(before) => before,
+    };
     let row = query_as!(
         Dino,
         r#"
         UPDATE dinos SET name = $2, weight = $3, diet = $4, user_id = $5
-        WHERE id = $1
+        WHERE id = $1 AND deleted_at IS NULL
         returning id, name, weight, diet, user_id
         "#,
         id,
@@ -85,7 +313,86 @@ pub async fn update(id: Uuid, dino: Dino, db_pool: &PgPool) -> tide::Result<Opti
         dino.diet,
         dino.user_id
     )
-    .fetch_optional(db_pool)
+    .fetch_one(&mut tx)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    audit::record("update", Some(&before), Some(&row), actor, &mut tx).await?;
+    revision::record(Some(&before), &row, actor, &mut tx).await?;
+    events::notify(DinoEventKind::Updated, &row, &mut tx).await?;
+    webhook::enqueue(DinoEventKind::Updated, &row, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Some(row))
+}
+
+pub async fn patch<'c, A: Acquire<'c, Database = Postgres>>(
+    id: Uuid,
+    changes: DinoChanges,
+    actor: &Actor,
+    conn: A,
+) -> tide::Result<Option<Dino>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_patch"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_patch", "UPDATE dinos");
+    validate_changes(&changes)?;
+    let (set_user_id, user_id) = match changes.user_id {
+        Some(user_id) => (true, user_id),
+        None => (false, None),
+    };
+    let mut tx = conn.begin().await?;
+    let before = match lock(id, &mut tx).await? {
+        None => return Ok(None),
+        Some(before) => before,
+    };
+    let row = query_as!(
+        Dino,
+        r#"
+        UPDATE dinos SET
+            name = COALESCE($2, name),
+            weight = COALESCE($3, weight),
+            diet = COALESCE($4, diet),
+            user_id = CASE WHEN $5 THEN $6 ELSE user_id END
+        WHERE id = $1 AND deleted_at IS NULL
+        returning id, name, weight, diet, user_id
+        "#,
+        id,
+        changes.name,
+        changes.weight,
+        changes.diet,
+        set_user_id,
+        user_id
+    )
+    .fetch_one(&mut tx)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    audit::record("update", Some(&before), Some(&row), actor, &mut tx).await?;
+    revision::record(Some(&before), &row, actor, &mut tx).await?;
+    events::notify(DinoEventKind::Updated, &row, &mut tx).await?;
+    webhook::enqueue(DinoEventKind::Updated, &row, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Some(row))
+}
+
+// The current dino, locked until the end of the transaction.
+async fn lock<'e, E: PgExecutor<'e>>(id: Uuid, executor: E) -> tide::Result<Option<Dino>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["dino_lock"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "dino_lock", "SELECT FROM dinos FOR UPDATE");
+    let row = query_as!(
+        Dino,
+        r#"
+        SELECT id, name, weight, diet, user_id from dinos
+        WHERE id = $1 AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(executor)
     .await
     .map_err(|e| Error::new(409, e))?;
 
@@ -0,0 +1,126 @@
+use super::*;
+use sqlx::{query, query_as, PgPool};
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub request_body: String,
+    pub status: Option<i32>,
+    pub response_body: Option<String>,
+    pub location: Option<String>,
+}
+
+// a reservation still in progress after that long is from a request that died, it's freed
+static LOCK_TIMEOUT_SECS: f64 = 60.0;
+
+// a reservation freed between the insert and the read is claimed again, that many times at most
+static RESERVE_ATTEMPTS: usize = 3;
+
+// Claims `key` for this request. Returns `None` when the key is new, or the
+// record stored by a previous request with the same key.
+pub async fn reserve(
+    key: &str,
+    user_id: &str,
+    request_body: &str,
+    ttl_secs: i64,
+    db_pool: &PgPool,
+) -> tide::Result<Option<IdempotencyRecord>> {
+    // expired keys and stale reservations can be reused
+    query!(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE created_at < now() - $1 * interval '1 second'
+        OR (status IS NULL AND created_at < now() - $2 * interval '1 second')
+        "#,
+        ttl_secs as f64,
+        LOCK_TIMEOUT_SECS
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    for _ in 0..RESERVE_ATTEMPTS {
+        let inserted = query!(
+            r#"
+            INSERT INTO idempotency_keys (key, user_id, request_body) VALUES
+            ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            returning key
+            "#,
+            key,
+            user_id,
+            request_body
+        )
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        if inserted.is_some() {
+            return Ok(None);
+        }
+
+        let record = query_as!(
+            IdempotencyRecord,
+            r#"
+            SELECT request_body, status, response_body, location from idempotency_keys
+            WHERE key = $1 AND user_id = $2
+            "#,
+            key,
+            user_id
+        )
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        if record.is_some() {
+            return Ok(record);
+        }
+    }
+
+    Err(Error::from_str(
+        409,
+        "A request with this Idempotency-Key is still in progress",
+    ))
+}
+
+pub async fn complete(
+    key: &str,
+    user_id: &str,
+    status: i32,
+    response_body: &str,
+    location: Option<&str>,
+    db_pool: &PgPool,
+) -> tide::Result<()> {
+    query!(
+        r#"
+        UPDATE idempotency_keys SET status = $3, response_body = $4, location = $5
+        WHERE key = $1 AND user_id = $2
+        "#,
+        key,
+        user_id,
+        status,
+        response_body,
+        location
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// Frees `key` after a failed request, so it can be retried.
+pub async fn release(key: &str, user_id: &str, db_pool: &PgPool) -> tide::Result<()> {
+    query!(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE key = $1 AND user_id = $2
+        "#,
+        key,
+        user_id
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
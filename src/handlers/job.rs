@@ -0,0 +1,326 @@
+use super::*;
+use crate::metrics::DB_QUERY_DURATION;
+use crate::telemetry::{query_span, POSTGRESQL};
+use serde_json::Value;
+use sqlx::{query, query_as, PgExecutor};
+
+// A unit of background work, `pending` until it's `done` or `failed` for good.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct JobRecord {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: Value,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: chrono::DateTime<chrono::Utc>,
+    pub last_error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// A job claimed by a worker, `attempts` counts the current one.
+#[derive(Debug, Clone)]
+pub struct ClaimedJob {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: Value,
+    pub attempts: i32,
+    pub max_attempts: i32,
+}
+
+// A job enqueued again and again on a schedule.
+#[derive(Debug, Clone)]
+pub struct RecurringJob {
+    pub name: String,
+    pub kind: String,
+    pub payload: Value,
+    pub schedule: String,
+}
+
+pub async fn enqueue<'e, E: PgExecutor<'e>>(
+    kind: &str,
+    payload: Value,
+    max_attempts: i32,
+    run_at: chrono::DateTime<chrono::Utc>,
+    executor: E,
+) -> tide::Result<Uuid> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_enqueue"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "job_enqueue", "INSERT INTO jobs");
+    let row = query!(
+        r#"
+        INSERT INTO jobs (kind, payload, max_attempts, run_at) VALUES
+        ($1, $2, $3, $4)
+        returning id
+        "#,
+        kind,
+        payload,
+        max_attempts,
+        run_at
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row.id)
+}
+
+pub async fn get<'e, E: PgExecutor<'e>>(id: Uuid, executor: E) -> tide::Result<Option<JobRecord>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_get"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "job_get", "SELECT FROM jobs");
+    let row = query_as!(
+        JobRecord,
+        r#"
+        SELECT id, kind, payload, status, attempts, max_attempts, run_at, last_error, created_at,
+               finished_at
+        from jobs
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+// Removes a job that hasn't started yet.
+pub async fn cancel<'e, E: PgExecutor<'e>>(id: Uuid, executor: E) -> tide::Result<Option<()>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_cancel"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "job_cancel", "DELETE FROM jobs");
+    let row = query!(
+        r#"
+        DELETE FROM jobs
+        WHERE id = $1 AND status = 'pending'
+        returning id
+        "#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row.map(|_| ()))
+}
+
+// Takes the oldest due job of one of `kinds`. A job still running after `timeout_secs` is
+// considered abandoned by a crashed worker and taken again.
+pub async fn claim<'e, E: PgExecutor<'e>>(
+    kinds: &[String],
+    timeout_secs: i64,
+    executor: E,
+) -> tide::Result<Option<ClaimedJob>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_claim"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "job_claim", "UPDATE jobs");
+    let row = query_as!(
+        ClaimedJob,
+        r#"
+        UPDATE jobs
+        SET status = 'running', attempts = attempts + 1, locked_at = now()
+        WHERE id = (
+            SELECT id from jobs
+            WHERE kind = ANY($1) AND run_at <= now()
+              AND (status = 'pending'
+                   OR (status = 'running' AND locked_at < now() - make_interval(secs => $2)))
+            ORDER BY run_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        returning id, kind, payload, attempts, max_attempts
+        "#,
+        kinds,
+        timeout_secs as f64
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+pub async fn done<'e, E: PgExecutor<'e>>(id: Uuid, executor: E) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_done"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "job_done", "UPDATE jobs");
+    query!(
+        r#"
+        UPDATE jobs
+        SET status = 'done', locked_at = NULL, last_error = NULL, finished_at = now()
+        WHERE id = $1
+        "#,
+        id
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// Records a failed attempt, the job runs again in `retry_in_secs` or has failed when `None`.
+pub async fn failed<'e, E: PgExecutor<'e>>(
+    id: Uuid,
+    error: &str,
+    retry_in_secs: Option<i64>,
+    executor: E,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_failed"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "job_failed", "UPDATE jobs");
+    query!(
+        r#"
+        UPDATE jobs
+        SET status = CASE WHEN $3::bigint IS NULL THEN 'failed' ELSE 'pending' END,
+            locked_at = NULL, last_error = $2,
+            run_at = now() + make_interval(secs => COALESCE($3, 0)),
+            finished_at = CASE WHEN $3::bigint IS NULL THEN now() END
+        WHERE id = $1
+        "#,
+        id,
+        error,
+        retry_in_secs
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// Deletes the finished jobs older than the retention, returns how many.
+pub async fn purge<'e, E: PgExecutor<'e>>(retention_secs: i64, executor: E) -> tide::Result<u64> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_purge"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "job_purge", "DELETE FROM jobs");
+    let result = query!(
+        r#"
+        DELETE FROM jobs
+        WHERE status IN ('done', 'failed')
+          AND finished_at < now() - make_interval(secs => $1)
+        "#,
+        retention_secs as f64
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(result.rows_affected())
+}
+
+// Declares a recurring job, its next run is only recomputed when its schedule changed.
+pub async fn upsert_recurring<'e, E: PgExecutor<'e>>(
+    job: &RecurringJob,
+    next_run_at: chrono::DateTime<chrono::Utc>,
+    executor: E,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_upsert_recurring"])
+        .start_timer();
+    let _span = query_span(
+        POSTGRESQL,
+        "job_upsert_recurring",
+        "INSERT INTO recurring_jobs",
+    );
+    query!(
+        r#"
+        INSERT INTO recurring_jobs (name, kind, payload, schedule, next_run_at) VALUES
+        ($1, $2, $3, $4, $5)
+        ON CONFLICT (name) DO UPDATE
+        SET kind = EXCLUDED.kind, payload = EXCLUDED.payload, schedule = EXCLUDED.schedule,
+            next_run_at = CASE WHEN recurring_jobs.schedule = EXCLUDED.schedule
+                          THEN recurring_jobs.next_run_at ELSE EXCLUDED.next_run_at END
+        "#,
+        job.name,
+        job.kind,
+        job.payload,
+        job.schedule,
+        next_run_at
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// Locks the recurring jobs among `names` that are due, until the transaction of `executor` ends.
+pub async fn due_recurring<'e, E: PgExecutor<'e>>(
+    names: &[String],
+    executor: E,
+) -> tide::Result<Vec<RecurringJob>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_due_recurring"])
+        .start_timer();
+    let _span = query_span(
+        POSTGRESQL,
+        "job_due_recurring",
+        "SELECT FROM recurring_jobs",
+    );
+    let rows = query_as!(
+        RecurringJob,
+        r#"
+        SELECT name, kind, payload, schedule from recurring_jobs
+        WHERE name = ANY($1) AND next_run_at <= now()
+        FOR UPDATE SKIP LOCKED
+        "#,
+        names
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(rows)
+}
+
+// Enqueues the next run of a recurring job, unless the previous one hasn't finished yet. The
+// runs that succeeded are dropped, only the failed ones are kept until `purge`.
+pub async fn run_recurring<'e, E: PgExecutor<'e>>(
+    job: &RecurringJob,
+    max_attempts: i32,
+    next_run_at: chrono::DateTime<chrono::Utc>,
+    executor: E,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["job_run_recurring"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "job_run_recurring", "INSERT INTO jobs");
+    query!(
+        r#"
+        WITH next AS (
+            UPDATE recurring_jobs SET next_run_at = $5
+            WHERE name = $1
+        ), previous AS (
+            DELETE FROM jobs
+            WHERE kind = $2 AND payload = $3 AND status = 'done'
+        )
+        INSERT INTO jobs (kind, payload, max_attempts)
+        SELECT $2, $3, $4
+        WHERE NOT EXISTS (
+            SELECT 1 from jobs
+            WHERE kind = $2 AND payload = $3 AND status IN ('pending', 'running')
+        )
+        "#,
+        job.name,
+        job.kind,
+        job.payload,
+        max_attempts,
+        next_run_at
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
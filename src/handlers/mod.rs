@@ -1,3 +1,10 @@
 use super::*;
 
+pub mod audit;
 pub mod dino;
+pub mod idempotency;
+pub mod job;
+pub mod revision;
+pub mod session;
+pub mod user;
+pub mod webhook;
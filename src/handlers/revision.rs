@@ -0,0 +1,135 @@
+use super::*;
+use crate::handlers::audit::Actor;
+use crate::metrics::DB_QUERY_DURATION;
+use crate::telemetry::{query_span, POSTGRESQL};
+use crate::Dino;
+use sqlx::{query, query_as, PgConnection, PgExecutor};
+
+// A full snapshot of a dino, taken every time it changes.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Revision {
+    pub dino_id: Uuid,
+    pub rev: i32,
+    pub name: String,
+    pub weight: i32,
+    pub diet: String,
+    pub user_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Revision {
+    pub fn dino(&self) -> Dino {
+        Dino {
+            id: self.dino_id,
+            name: self.name.clone(),
+            weight: self.weight,
+            diet: self.diet.clone(),
+            user_id: self.user_id.clone(),
+        }
+    }
+}
+
+// Saves `after` as the next revision of the dino. Dinos created before revisions were kept
+// get `before` as their first one.
+pub async fn record(
+    before: Option<&Dino>,
+    after: &Dino,
+    actor: &Actor,
+    conn: &mut PgConnection,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["revision_record"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "revision_record", "INSERT INTO dino_revisions");
+
+    let last = query!(
+        r#"
+        SELECT MAX(rev) as rev from dino_revisions
+        WHERE dino_id = $1
+        "#,
+        after.id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| Error::new(409, e))?
+    .rev;
+
+    let mut snapshots = vec![];
+    if let (None, Some(before)) = (last, before) {
+        snapshots.push((before, None));
+    }
+    snapshots.push((after, actor.user_id.as_deref()));
+
+    let mut rev = last.unwrap_or(0);
+    for (dino, actor_id) in snapshots {
+        rev += 1;
+        query!(
+            r#"
+            INSERT INTO dino_revisions (dino_id, rev, name, weight, diet, user_id, actor_id) VALUES
+            ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            dino.id,
+            rev,
+            dino.name,
+            dino.weight,
+            dino.diet,
+            dino.user_id,
+            actor_id
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+    }
+
+    Ok(())
+}
+
+pub async fn list<'e, E: PgExecutor<'e>>(
+    dino_id: Uuid,
+    executor: E,
+) -> tide::Result<Vec<Revision>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["revision_list"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "revision_list", "SELECT FROM dino_revisions");
+    let rows = query_as!(
+        Revision,
+        r#"
+        SELECT dino_id, rev, name, weight, diet, user_id, actor_id, created_at from dino_revisions
+        WHERE dino_id = $1
+        ORDER BY rev
+        "#,
+        dino_id
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(rows)
+}
+
+pub async fn get<'e, E: PgExecutor<'e>>(
+    dino_id: Uuid,
+    rev: i32,
+    executor: E,
+) -> tide::Result<Option<Revision>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["revision_get"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "revision_get", "SELECT FROM dino_revisions");
+    let row = query_as!(
+        Revision,
+        r#"
+        SELECT dino_id, rev, name, weight, diet, user_id, actor_id, created_at from dino_revisions
+        WHERE dino_id = $1 AND rev = $2
+        "#,
+        dino_id,
+        rev
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
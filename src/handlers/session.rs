@@ -0,0 +1,121 @@
+use super::*;
+use crate::metrics::DB_QUERY_DURATION;
+use crate::telemetry::{query_span, POSTGRESQL};
+use serde_json::Value;
+use sqlx::{query, PgExecutor};
+
+pub async fn load<'e, E: PgExecutor<'e>>(id: &str, executor: E) -> tide::Result<Option<Value>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["session_load"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "session_load", "SELECT FROM sessions");
+    let row = query!(
+        r#"
+        SELECT session from sessions
+        WHERE id = $1 AND (expires_at IS NULL OR expires_at > now())
+        "#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row.map(|row| row.session))
+}
+
+pub async fn store<'e, E: PgExecutor<'e>>(
+    id: &str,
+    user_id: Option<String>,
+    session: Value,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    executor: E,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["session_store"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "session_store", "INSERT INTO sessions");
+    query!(
+        r#"
+        INSERT INTO sessions (id, user_id, session, expires_at) VALUES
+        ($1, $2, $3, $4)
+        ON CONFLICT (id) DO UPDATE
+        SET user_id = EXCLUDED.user_id, session = EXCLUDED.session,
+            expires_at = EXCLUDED.expires_at
+        "#,
+        id,
+        user_id,
+        session,
+        expires_at
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+pub async fn destroy<'e, E: PgExecutor<'e>>(id: &str, executor: E) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["session_destroy"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "session_destroy", "DELETE FROM sessions");
+    query!("DELETE FROM sessions WHERE id = $1", id)
+        .execute(executor)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// Deletes the expired sessions, or every one with `all`, returns how many.
+pub async fn purge<'e, E: PgExecutor<'e>>(all: bool, executor: E) -> tide::Result<u64> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["session_purge"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "session_purge", "DELETE FROM sessions");
+    let result = query!(
+        r#"
+        DELETE FROM sessions
+        WHERE $1 OR expires_at <= now()
+        "#,
+        all
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(result.rows_affected())
+}
+
+// Logs a user out everywhere, returns how many sessions they had.
+pub async fn purge_user<'e, E: PgExecutor<'e>>(user_id: &str, executor: E) -> tide::Result<u64> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["session_purge_user"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "session_purge_user", "DELETE FROM sessions");
+    let result = query!("DELETE FROM sessions WHERE user_id = $1", user_id)
+        .execute(executor)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+    Ok(result.rows_affected())
+}
+
+// The sessions that haven't expired.
+pub async fn count<'e, E: PgExecutor<'e>>(executor: E) -> tide::Result<i64> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["session_count"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "session_count", "SELECT FROM sessions");
+    let row = query!(
+        r#"
+        SELECT COUNT(*) as "count!" from sessions
+        WHERE expires_at IS NULL OR expires_at > now()
+        "#
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row.count)
+}
@@ -0,0 +1,142 @@
+use super::*;
+use crate::metrics::DB_QUERY_DURATION;
+use crate::telemetry::{query_span, POSTGRESQL};
+use sqlx::{query_as, PgExecutor};
+
+pub static ADMIN: &str = "admin";
+
+// Someone who logged in, or was created by an admin ahead of their first login.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct User {
+    pub id: String,
+    pub name: Option<String>,
+    // `user` or `admin`
+    pub role: String,
+    // a disabled user can't log in
+    pub disabled: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Records a login, keeping the name up to date.
+pub async fn login<'e, E: PgExecutor<'e>>(id: &str, name: &str, executor: E) -> tide::Result<User> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["user_login"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "user_login", "INSERT INTO users");
+    let row = query_as!(
+        User,
+        r#"
+        INSERT INTO users (id, name) VALUES
+        ($1, $2)
+        ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name
+        returning id, name, role, disabled, created_at
+        "#,
+        id,
+        name
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+pub async fn get<'e, E: PgExecutor<'e>>(id: &str, executor: E) -> tide::Result<Option<User>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["user_get"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "user_get", "SELECT FROM users");
+    let row = query_as!(
+        User,
+        r#"
+        SELECT id, name, role, disabled, created_at from users
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+pub async fn create<'e, E: PgExecutor<'e>>(
+    id: &str,
+    name: Option<&str>,
+    role: &str,
+    executor: E,
+) -> tide::Result<User> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["user_create"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "user_create", "INSERT INTO users");
+    let row = query_as!(
+        User,
+        r#"
+        INSERT INTO users (id, name, role) VALUES
+        ($1, $2, $3)
+        returning id, name, role, disabled, created_at
+        "#,
+        id,
+        name,
+        role
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+pub async fn set_role<'e, E: PgExecutor<'e>>(
+    id: &str,
+    role: &str,
+    executor: E,
+) -> tide::Result<Option<User>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["user_set_role"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "user_set_role", "UPDATE users");
+    let row = query_as!(
+        User,
+        r#"
+        UPDATE users SET role = $2
+        WHERE id = $1
+        returning id, name, role, disabled, created_at
+        "#,
+        id,
+        role
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+pub async fn set_disabled<'e, E: PgExecutor<'e>>(
+    id: &str,
+    disabled: bool,
+    executor: E,
+) -> tide::Result<Option<User>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["user_set_disabled"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "user_set_disabled", "UPDATE users");
+    let row = query_as!(
+        User,
+        r#"
+        UPDATE users SET disabled = $2
+        WHERE id = $1
+        returning id, name, role, disabled, created_at
+        "#,
+        id,
+        disabled
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
@@ -0,0 +1,314 @@
+use super::*;
+use crate::events::DinoEventKind;
+use crate::metrics::DB_QUERY_DURATION;
+use crate::telemetry::{query_span, POSTGRESQL};
+use crate::Dino;
+use serde_json::Value;
+use sqlx::{query, query_as, PgExecutor};
+
+// A url notified of the dino changes matching its filters.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub user_id: String,
+    pub url: String,
+    // the key of the HMAC-SHA256 signature of every delivery
+    pub secret: String,
+    // the kinds of events delivered, `created`, `updated` or `deleted`
+    pub events: Vec<String>,
+    // only the changes of dinos with this diet when set
+    pub diet: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// A webhook as listed, the secret is only ever shown once, when it's created.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct WebhookSummary {
+    pub id: Uuid,
+    pub user_id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub diet: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// An event in the outbox of a webhook, `pending` until it's `delivered` or `dead`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub dino: Value,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: chrono::DateTime<chrono::Utc>,
+    // the status of the last response, none when the request itself failed
+    pub last_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub delivered_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// A delivery due to be sent, with where to and how to sign it.
+#[derive(Debug, Clone)]
+pub struct PendingDelivery {
+    pub id: Uuid,
+    pub event: String,
+    pub dino: Value,
+    pub attempts: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub url: String,
+    pub secret: String,
+}
+
+pub async fn create<'e, E: PgExecutor<'e>>(
+    webhook: &Webhook,
+    executor: E,
+) -> tide::Result<Webhook> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_create"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "webhook_create", "INSERT INTO webhooks");
+    let row = query_as!(
+        Webhook,
+        r#"
+        INSERT INTO webhooks (id, user_id, url, secret, events, diet) VALUES
+        ($1, $2, $3, $4, $5, $6)
+        returning id, user_id, url, secret, events, diet, created_at
+        "#,
+        webhook.id,
+        webhook.user_id,
+        webhook.url,
+        webhook.secret,
+        &webhook.events,
+        webhook.diet
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+pub async fn list<'e, E: PgExecutor<'e>>(
+    user_id: &str,
+    executor: E,
+) -> tide::Result<Vec<WebhookSummary>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_list"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "webhook_list", "SELECT FROM webhooks");
+    let rows = query_as!(
+        WebhookSummary,
+        r#"
+        SELECT id, user_id, url, events, diet, created_at from webhooks
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(rows)
+}
+
+pub async fn get<'e, E: PgExecutor<'e>>(id: Uuid, executor: E) -> tide::Result<Option<Webhook>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_get"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "webhook_get", "SELECT FROM webhooks");
+    let row = query_as!(
+        Webhook,
+        r#"
+        SELECT id, user_id, url, secret, events, diet, created_at from webhooks
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row)
+}
+
+// Deletes a webhook with its outbox.
+pub async fn delete<'e, E: PgExecutor<'e>>(id: Uuid, executor: E) -> tide::Result<Option<()>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_delete"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "webhook_delete", "DELETE FROM webhooks");
+    let row = query!(
+        r#"
+        DELETE FROM webhooks
+        WHERE id = $1
+        returning id
+        "#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(row.map(|_| ()))
+}
+
+// Adds a change to the outbox of every webhook interested in it. Called in the transaction of
+// the change, so the event is delivered if and only if the change is committed.
+pub async fn enqueue<'e, E: PgExecutor<'e>>(
+    kind: DinoEventKind,
+    dino: &Dino,
+    executor: E,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_enqueue"])
+        .start_timer();
+    let _span = query_span(
+        POSTGRESQL,
+        "webhook_enqueue",
+        "INSERT INTO webhook_deliveries",
+    );
+    query!(
+        r#"
+        INSERT INTO webhook_deliveries (webhook_id, event, dino)
+        SELECT id, $1, $2 from webhooks
+        WHERE $1 = ANY(events) AND (diet IS NULL OR diet = $3)
+        "#,
+        kind.as_str(),
+        serde_json::to_value(dino)?,
+        dino.diet
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// The delivery log of a webhook, the newest first.
+pub async fn deliveries<'e, E: PgExecutor<'e>>(
+    webhook_id: Uuid,
+    executor: E,
+) -> tide::Result<Vec<Delivery>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_deliveries"])
+        .start_timer();
+    let _span = query_span(
+        POSTGRESQL,
+        "webhook_deliveries",
+        "SELECT FROM webhook_deliveries",
+    );
+    let rows = query_as!(
+        Delivery,
+        r#"
+        SELECT id, webhook_id, event, dino, status, attempts, next_attempt_at, last_status,
+               last_error, created_at, delivered_at
+        from webhook_deliveries
+        WHERE webhook_id = $1
+        ORDER BY created_at DESC
+        "#,
+        webhook_id
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(rows)
+}
+
+// Claims the pending deliveries that are due, oldest first, by pushing their next attempt
+// `lease_secs` away: other instances skip them while they're sent, and they're due again should
+// this one never record how the attempt went.
+pub async fn claim<'e, E: PgExecutor<'e>>(
+    limit: i64,
+    lease_secs: i64,
+    executor: E,
+) -> tide::Result<Vec<PendingDelivery>> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_claim"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "webhook_claim", "UPDATE webhook_deliveries");
+    let rows = query_as!(
+        PendingDelivery,
+        r#"
+        UPDATE webhook_deliveries d
+        SET next_attempt_at = now() + make_interval(secs => $2)
+        FROM webhooks w
+        WHERE w.id = d.webhook_id AND d.id IN (
+            SELECT id from webhook_deliveries
+            WHERE status = 'pending' AND next_attempt_at <= now()
+            ORDER BY next_attempt_at
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        returning d.id, d.event, d.dino, d.attempts, d.created_at, w.url, w.secret
+        "#,
+        limit,
+        lease_secs as f64
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(rows)
+}
+
+pub async fn delivered<'e, E: PgExecutor<'e>>(
+    id: Uuid,
+    status: i32,
+    executor: E,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_delivered"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "webhook_delivered", "UPDATE webhook_deliveries");
+    query!(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = 'delivered', attempts = attempts + 1, last_status = $2, last_error = NULL,
+            delivered_at = now()
+        WHERE id = $1
+        "#,
+        id,
+        status
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
+
+// Records a failed attempt, the delivery is retried in `retry_in_secs` or dead when `None`.
+pub async fn failed<'e, E: PgExecutor<'e>>(
+    id: Uuid,
+    status: Option<i32>,
+    error: &str,
+    retry_in_secs: Option<i64>,
+    executor: E,
+) -> tide::Result<()> {
+    let _timer = DB_QUERY_DURATION
+        .with_label_values(&["webhook_failed"])
+        .start_timer();
+    let _span = query_span(POSTGRESQL, "webhook_failed", "UPDATE webhook_deliveries");
+    query!(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = CASE WHEN $4::bigint IS NULL THEN 'dead' ELSE 'pending' END,
+            attempts = attempts + 1, last_status = $2, last_error = $3,
+            next_attempt_at = now() + make_interval(secs => COALESCE($4, 0))
+        WHERE id = $1
+        "#,
+        id,
+        status,
+        error,
+        retry_in_secs
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::new(409, e))?;
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use super::*;
+
+use tide::http::{headers, mime};
+use tide::{Body, Request, Response};
+
+use crate::handlers;
+
+pub static IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub static IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+// Wraps an endpoint so requests sent with an `Idempotency-Key` header are only
+// processed once per user and key; retries get the stored response back. The keys
+// belong to logged in users, anonymous clients can't tell their keys apart.
+pub fn idempotent<E>(endpoint: E) -> Idempotent<E> {
+    Idempotent { endpoint }
+}
+
+#[derive(Debug)]
+pub struct Idempotent<E> {
+    endpoint: E,
+}
+
+#[tide::utils::async_trait]
+impl<E: Endpoint<State>> Endpoint<State> for Idempotent<E> {
+    async fn call(&self, mut req: Request<State>) -> tide::Result {
+        let key = match req.header(IDEMPOTENCY_KEY_HEADER) {
+            None => return self.endpoint.call(req).await,
+            Some(key) => key.as_str().to_string(),
+        };
+        if key.is_empty() || key.len() > 255 {
+            return Err(Error::from_str(
+                400,
+                "Idempotency-Key must be between 1 and 255 characters",
+            ));
+        }
+
+        let user_id: String = match req.session().get("user_id") {
+            None => {
+                return Err(Error::from_str(
+                    401,
+                    "Idempotency-Key requires a logged in user",
+                ))
+            }
+            Some(user_id) => user_id,
+        };
+
+        let body = req.body_string().await?;
+        req.set_body(body.clone());
+
+        let db_pool = req.state().db_pool.clone();
+        let ttl_secs = req.state().idempotency_ttl_secs;
+
+        let record =
+            handlers::idempotency::reserve(&key, &user_id, &body, ttl_secs, &db_pool).await?;
+        if let Some(record) = record {
+            if record.request_body != body {
+                return Err(Error::from_str(
+                    422,
+                    "Idempotency-Key was already used with a different request",
+                ));
+            }
+
+            let status = match record.status {
+                None => {
+                    return Err(Error::from_str(
+                        409,
+                        "A request with this Idempotency-Key is still in progress",
+                    ))
+                }
+                Some(status) => status as u16,
+            };
+
+            let mut res = Response::new(status);
+            res.insert_header(IDEMPOTENT_REPLAYED_HEADER, "true");
+            if let Some(location) = record.location {
+                res.insert_header(headers::LOCATION, location);
+            }
+            if let Some(response_body) = record.response_body.filter(|b| !b.is_empty()) {
+                let mut body = Body::from_string(response_body);
+                body.set_mime(mime::JSON);
+                res.set_body(body);
+            }
+            return Ok(res);
+        }
+
+        match self.endpoint.call(req).await {
+            Ok(mut res) if !res.status().is_server_error() => {
+                let body = res.take_body();
+                let content_type = body.mime().clone();
+                let response_body = body.into_string().await?;
+                let location = res
+                    .header(headers::LOCATION)
+                    .map(|location| location.as_str().to_string());
+
+                handlers::idempotency::complete(
+                    &key,
+                    &user_id,
+                    u16::from(res.status()) as i32,
+                    &response_body,
+                    location.as_deref(),
+                    &db_pool,
+                )
+                .await?;
+
+                let mut body = Body::from_string(response_body);
+                body.set_mime(content_type);
+                res.set_body(body);
+                Ok(res)
+            }
+            res => {
+                handlers::idempotency::release(&key, &user_id, &db_pool).await?;
+                res
+            }
+        }
+    }
+}
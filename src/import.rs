@@ -0,0 +1,194 @@
+use super::*;
+
+use async_std::io::{prelude::BufReadExt, BufRead, Lines};
+use async_std::stream::StreamExt;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ImportFormat {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            "text/csv" => Some(ImportFormat::Csv),
+            "application/x-ndjson" | "application/ndjson" => Some(ImportFormat::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportQuery {
+    pub format: Option<ImportFormat>,
+    #[serde(default)]
+    pub dry_run: bool,
+    // update the dinos that already exist instead of failing the row
+    #[serde(default)]
+    pub upsert: bool,
+    // dino field -> csv column, e.g. `mapping[weight]=Mass (kg)`
+    #[serde(default)]
+    pub mapping: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ImportError {
+    pub row: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub committed: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub errors: Vec<ImportError>,
+}
+
+// the position of each dino field in a csv record
+#[derive(Debug, Clone)]
+struct CsvColumns {
+    id: Option<usize>,
+    name: usize,
+    weight: usize,
+    diet: usize,
+}
+
+impl CsvColumns {
+    fn from_header(header: &[String], mapping: &HashMap<String, String>) -> tide::Result<Self> {
+        let position = |field: &str| {
+            let column = mapping.get(field).map(String::as_str).unwrap_or(field);
+            header
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(column.trim()))
+        };
+        let required = |field: &str| {
+            position(field)
+                .ok_or_else(|| Error::from_str(422, format!("missing csv column for `{}`", field)))
+        };
+
+        Ok(CsvColumns {
+            id: position("id"),
+            name: required("name")?,
+            weight: required("weight")?,
+            diet: required("diet")?,
+        })
+    }
+
+    fn dino(&self, record: &[String]) -> Result<NewDino, String> {
+        let field = |index: usize| {
+            record
+                .get(index)
+                .map(|value| value.trim())
+                .ok_or_else(|| format!("missing value in column {}", index + 1))
+        };
+
+        let id = match self.id.map(field).transpose()? {
+            None | Some("") => None,
+            Some(id) => Some(Uuid::parse_str(id).map_err(|e| format!("invalid id: {}", e))?),
+        };
+        let weight = field(self.weight)?
+            .parse()
+            .map_err(|e| format!("invalid weight: {}", e))?;
+
+        Ok(NewDino {
+            id,
+            name: field(self.name)?.to_string(),
+            weight,
+            diet: field(self.diet)?.to_string(),
+        })
+    }
+}
+
+// Decodes the dinos of an upload one row at a time.
+pub struct DinoRows<R> {
+    lines: Lines<R>,
+    columns: Option<CsvColumns>,
+    row: usize,
+}
+
+impl<R: BufRead + Unpin> DinoRows<R> {
+    pub async fn new(
+        reader: R,
+        format: ImportFormat,
+        mapping: &HashMap<String, String>,
+    ) -> tide::Result<Self> {
+        let mut rows = DinoRows {
+            lines: reader.lines(),
+            columns: None,
+            row: 0,
+        };
+
+        if format == ImportFormat::Csv {
+            let header = match rows.next_csv_record().await? {
+                None => return Err(Error::from_str(422, "missing csv header")),
+                Some(header) => header,
+            };
+            rows.columns = Some(CsvColumns::from_header(&header, mapping)?);
+        }
+
+        Ok(rows)
+    }
+
+    // The next row number and its dino, or why it can't be decoded.
+    pub async fn next(&mut self) -> tide::Result<Option<(usize, Result<NewDino, String>)>> {
+        let dino = match self.columns.clone() {
+            Some(columns) => match self.next_csv_record().await? {
+                None => return Ok(None),
+                Some(record) => columns.dino(&record),
+            },
+            None => match self.next_line().await? {
+                None => return Ok(None),
+                Some(line) => serde_json::from_str(&line).map_err(|e| e.to_string()),
+            },
+        };
+
+        self.row += 1;
+        Ok(Some((self.row, dino)))
+    }
+
+    async fn next_line(&mut self) -> tide::Result<Option<String>> {
+        while let Some(line) = self.lines.next().await {
+            let line = line?;
+            if !line.trim().is_empty() {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+
+    // A quoted field can hold line breaks, so a record spans lines until its quotes are balanced.
+    async fn next_csv_record(&mut self) -> tide::Result<Option<Vec<String>>> {
+        let mut record = match self.next_line().await? {
+            None => return Ok(None),
+            Some(line) => line,
+        };
+        while record.matches('"').count() % 2 != 0 {
+            match self.lines.next().await {
+                None => break,
+                Some(line) => {
+                    record.push('\n');
+                    record.push_str(&line?);
+                }
+            }
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(record.as_bytes());
+        let fields = match reader.records().next() {
+            None => vec![],
+            Some(fields) => fields
+                .map_err(|e| Error::new(422, e))?
+                .iter()
+                .map(String::from)
+                .collect(),
+        };
+
+        Ok(Some(fields))
+    }
+}
@@ -0,0 +1,53 @@
+use super::*;
+
+use serde::de::DeserializeOwned;
+use sqlx::PgExecutor;
+use std::time::Duration;
+
+pub mod schedule;
+pub mod tasks;
+pub mod worker;
+
+pub use schedule::Schedule;
+pub use worker::Worker;
+
+// What the jobs of a worker share.
+#[derive(Debug, Clone)]
+pub struct JobContext {
+    pub db_pool: PgPool,
+    pub http: surf::Client,
+}
+
+// A kind of background work, stored as its JSON until a worker runs it.
+#[tide::utils::async_trait]
+pub trait Job: Serialize + DeserializeOwned + Send + Sync + 'static {
+    // tells the worker which type a stored job is
+    const KIND: &'static str;
+
+    // the attempts before the job has failed for good
+    const MAX_ATTEMPTS: i32 = 5;
+
+    async fn run(self, cx: &JobContext) -> tide::Result<()>;
+
+    // the wait after the failed attempt number `attempt`, 10s doubling up to an hour
+    fn retry_in(attempt: i32) -> Duration {
+        let factor = 2u64.saturating_pow((attempt - 1).max(0) as u32);
+        Duration::from_secs(10u64.saturating_mul(factor).min(60 * 60))
+    }
+}
+
+// Queues a job to run as soon as a worker is free. Enqueued in a transaction, it only runs
+// when the transaction commits.
+pub async fn enqueue<'e, J: Job, E: PgExecutor<'e>>(job: &J, executor: E) -> tide::Result<Uuid> {
+    enqueue_at(job, chrono::Utc::now(), executor).await
+}
+
+// Queues a job to run once `run_at` has passed.
+pub async fn enqueue_at<'e, J: Job, E: PgExecutor<'e>>(
+    job: &J,
+    run_at: chrono::DateTime<chrono::Utc>,
+    executor: E,
+) -> tide::Result<Uuid> {
+    let payload = serde_json::to_value(job)?;
+    handlers::job::enqueue(J::KIND, payload, J::MAX_ATTEMPTS, run_at, executor).await
+}
@@ -0,0 +1,176 @@
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+// the furthest a cron expression is searched for its next match
+static CRON_HORIZON_DAYS: i64 = 366;
+
+// When a recurring job runs, `@every 30s` (or `m`, `h`) or a cron expression in UTC like
+// `0 3 * * *`, with `@hourly` and `@daily` as shortcuts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    // the first run strictly after `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Every(interval) => Some(after + *interval),
+            Schedule::Cron(cron) => cron.next_after(after),
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(every) = s.strip_prefix("@every") {
+            let every = every.trim();
+            // the unit is the last character, whatever its length in bytes
+            let (amount, unit) = match every.char_indices().last() {
+                Some((i, _)) => every.split_at(i),
+                None => return Err(format!("invalid interval `{}`", every)),
+            };
+            let amount: i64 = amount
+                .parse()
+                .map_err(|_| format!("invalid interval `{}`", every))?;
+            let interval = match unit {
+                "s" => Duration::seconds(amount),
+                "m" => Duration::minutes(amount),
+                "h" => Duration::hours(amount),
+                _ => return Err(format!("invalid interval unit in `{}`", every)),
+            };
+            if amount <= 0 {
+                return Err(format!("invalid interval `{}`", every));
+            }
+            return Ok(Schedule::Every(interval));
+        }
+
+        let expression = match s {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            expression => expression,
+        };
+        Ok(Schedule::Cron(expression.parse()?))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Every(interval) => write!(f, "@every {}s", interval.num_seconds()),
+            Schedule::Cron(cron) => write!(f, "{}", cron.expression),
+        }
+    }
+}
+
+// The minutes matched by a `minute hour day-of-month month day-of-week` expression. Fields are
+// `*`, numbers, ranges `a-b`, steps `*/n` or `a-b/n` and lists of those.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // a day matches either of the day fields when both are restricted, like in cron
+    any_day: bool,
+}
+
+impl Cron {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut t = after.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        let horizon = after + Duration::days(CRON_HORIZON_DAYS);
+        while t <= horizon {
+            if !self.matches_day(&t) {
+                t = t.duration_trunc(Duration::days(1)).ok()? + Duration::days(1);
+            } else if !bit(self.hours, t.hour()) {
+                t = t.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+            } else if !bit(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, t: &DateTime<Utc>) -> bool {
+        if !bit(self.months, t.month()) {
+            return false;
+        }
+        let day = bit(self.days, t.day());
+        let weekday = bit(self.weekdays, t.weekday().num_days_from_sunday());
+        if self.any_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("`{}` must have 5 fields", s));
+        }
+
+        // sunday is both 0 and 7
+        let mut weekdays = field(fields[4], 0, 7)?;
+        if bit(weekdays, 7) {
+            weekdays |= 1;
+        }
+        Ok(Cron {
+            expression: fields.join(" "),
+            minutes: field(fields[0], 0, 59)?,
+            hours: field(fields[1], 0, 23)?,
+            days: field(fields[2], 1, 31)?,
+            months: field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] != "*" && fields[4] != "*",
+        })
+    }
+}
+
+fn bit(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+fn field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let invalid = || format!("invalid cron field `{}`", field);
+    let number = |s: &str| -> Result<u32, String> {
+        s.parse()
+            .ok()
+            .filter(|n| (min..=max).contains(n))
+            .ok_or_else(invalid)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (number(from)?, number(to)?),
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if step == 0 || from > to {
+            return Err(invalid());
+        }
+        for value in (from..=to).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
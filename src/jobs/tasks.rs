@@ -0,0 +1,74 @@
+use super::*;
+
+use crate::webhooks::{self, RetryPolicy};
+
+// Empties the trash of the dinos deleted for longer than the retention.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeTrash {
+    pub retention_secs: i64,
+}
+
+#[tide::utils::async_trait]
+impl Job for PurgeTrash {
+    const KIND: &'static str = "purge_trash";
+
+    async fn run(self, cx: &JobContext) -> tide::Result<()> {
+        let purged = handlers::dino::purge(self.retention_secs, &cx.db_pool).await?;
+        tide::log::info!("trash purged", { dinos: purged });
+        Ok(())
+    }
+}
+
+// Sends the deliveries of the webhook outbox until it's empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliverWebhooks {
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub allow_private_targets: bool,
+}
+
+#[tide::utils::async_trait]
+impl Job for DeliverWebhooks {
+    const KIND: &'static str = "deliver_webhooks";
+
+    // the next run picks up where a failed one stopped
+    const MAX_ATTEMPTS: i32 = 1;
+
+    async fn run(self, cx: &JobContext) -> tide::Result<()> {
+        let allow_private = self.allow_private_targets;
+        while webhooks::dispatch(&cx.db_pool, &cx.http, &self.retry, allow_private).await? > 0 {}
+        Ok(())
+    }
+}
+
+// Deletes the expired sessions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeSessions {}
+
+#[tide::utils::async_trait]
+impl Job for PurgeSessions {
+    const KIND: &'static str = "purge_sessions";
+
+    async fn run(self, cx: &JobContext) -> tide::Result<()> {
+        let purged = handlers::session::purge(false, &cx.db_pool).await?;
+        tide::log::info!("expired sessions purged", { sessions: purged });
+        Ok(())
+    }
+}
+
+// Deletes the finished jobs older than the retention.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeJobs {
+    pub retention_secs: i64,
+}
+
+#[tide::utils::async_trait]
+impl Job for PurgeJobs {
+    const KIND: &'static str = "purge_jobs";
+
+    async fn run(self, cx: &JobContext) -> tide::Result<()> {
+        let purged = handlers::job::purge(self.retention_secs, &cx.db_pool).await?;
+        tide::log::info!("finished jobs purged", { jobs: purged });
+        Ok(())
+    }
+}
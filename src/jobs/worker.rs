@@ -0,0 +1,180 @@
+use super::*;
+
+use crate::handlers::job::RecurringJob;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+// how long an idle worker waits before looking for jobs again
+static POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// a job running for longer is considered abandoned by a crashed worker and run again
+static JOB_TIMEOUT_SECS: i64 = 15 * 60;
+
+type Handler = Box<
+    dyn Fn(Value, JobContext) -> Pin<Box<dyn Future<Output = tide::Result<()>> + Send>>
+        + Send
+        + Sync,
+>;
+
+struct Registered {
+    run: Handler,
+    retry_in: fn(i32) -> Duration,
+    max_attempts: i32,
+}
+
+// Runs the jobs of the kinds it knows, and enqueues the recurring ones when they're due.
+pub struct Worker {
+    db_pool: PgPool,
+    http: surf::Client,
+    jobs: HashMap<&'static str, Registered>,
+    recurring: Vec<(RecurringJob, Schedule)>,
+}
+
+impl Worker {
+    pub fn new(db_pool: PgPool) -> Self {
+        Worker {
+            db_pool,
+            http: surf::Client::new(),
+            jobs: HashMap::new(),
+            recurring: vec![],
+        }
+    }
+
+    pub fn register<J: Job>(mut self) -> Self {
+        let run: Handler = Box::new(|payload, cx| {
+            Box::pin(async move {
+                let job: J = serde_json::from_value(payload)?;
+                job.run(&cx).await
+            })
+        });
+        self.jobs.insert(
+            J::KIND,
+            Registered {
+                run,
+                retry_in: J::retry_in,
+                max_attempts: J::MAX_ATTEMPTS,
+            },
+        );
+        self
+    }
+
+    // Enqueues `job` on `schedule`, once across all the workers.
+    pub fn recurring<J: Job>(mut self, name: &str, schedule: Schedule, job: J) -> Self {
+        let recurring = RecurringJob {
+            name: name.to_string(),
+            kind: J::KIND.to_string(),
+            payload: serde_json::to_value(&job).expect("a job serializes to JSON"),
+            schedule: schedule.to_string(),
+        };
+        self.recurring.push((recurring, schedule));
+        self.register::<J>()
+    }
+
+    // Saves the recurring jobs, they're due right away the first time.
+    pub async fn start(&self) -> tide::Result<()> {
+        for (job, _) in &self.recurring {
+            handlers::job::upsert_recurring(job, chrono::Utc::now(), &self.db_pool).await?;
+        }
+        Ok(())
+    }
+
+    // Enqueues the recurring jobs that are due, returns how many were.
+    pub async fn enqueue_recurring(&self) -> tide::Result<usize> {
+        if self.recurring.is_empty() {
+            return Ok(0);
+        }
+        let names: Vec<String> = self
+            .recurring
+            .iter()
+            .map(|(job, _)| job.name.clone())
+            .collect();
+
+        let mut tx = self.db_pool.begin().await?;
+        let due = handlers::job::due_recurring(&names, &mut tx).await?;
+        for job in &due {
+            let schedule = self
+                .recurring
+                .iter()
+                .find(|(recurring, _)| recurring.name == job.name)
+                .map(|(_, schedule)| schedule);
+            // a schedule that never matches is looked at again a year later
+            let now = chrono::Utc::now();
+            let next_run_at = schedule
+                .and_then(|schedule| schedule.next_after(now))
+                .unwrap_or_else(|| now + chrono::Duration::days(366));
+            let max_attempts = self
+                .jobs
+                .get(job.kind.as_str())
+                .map_or(1, |j| j.max_attempts);
+            handlers::job::run_recurring(job, max_attempts, next_run_at, &mut tx).await?;
+        }
+        tx.commit().await?;
+
+        Ok(due.len())
+    }
+
+    // Runs the oldest due job, returns whether there was one.
+    pub async fn run_once(&self) -> tide::Result<bool> {
+        let kinds: Vec<String> = self.jobs.keys().map(|kind| kind.to_string()).collect();
+        let job = match handlers::job::claim(&kinds, JOB_TIMEOUT_SECS, &self.db_pool).await? {
+            None => return Ok(false),
+            Some(job) => job,
+        };
+        let registered = &self.jobs[job.kind.as_str()];
+        let cx = JobContext {
+            db_pool: self.db_pool.clone(),
+            http: self.http.clone(),
+        };
+
+        match (registered.run)(job.payload.clone(), cx).await {
+            Ok(()) => handlers::job::done(job.id, &self.db_pool).await?,
+            Err(e) => {
+                tide::log::warn!("job failed", {
+                    id: job.id.to_string(),
+                    kind: job.kind.as_str(),
+                    attempt: job.attempts,
+                    error: e.to_string(),
+                });
+                let retry_in = Some(job.attempts)
+                    .filter(|attempts| *attempts < job.max_attempts)
+                    .map(|attempts| (registered.retry_in)(attempts).as_secs() as i64);
+                handlers::job::failed(job.id, &e.to_string(), retry_in, &self.db_pool).await?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Runs `concurrency` loops taking jobs until the process exits.
+    pub async fn run(self, concurrency: usize) {
+        while let Err(e) = self.start().await {
+            tide::log::error!("can't start the worker", { error: e.to_string() });
+            async_std::task::sleep(POLL_INTERVAL).await;
+        }
+
+        let worker = Arc::new(self);
+        let loops: Vec<_> = (0..concurrency.max(1))
+            .map(|_| async_std::task::spawn(worker.clone().work()))
+            .collect();
+        for handle in loops {
+            handle.await;
+        }
+    }
+
+    async fn work(self: Arc<Self>) {
+        loop {
+            if let Err(e) = self.enqueue_recurring().await {
+                tide::log::error!("can't enqueue the recurring jobs", { error: e.to_string() });
+            }
+            match self.run_once().await {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => tide::log::error!("can't run the jobs", { error: e.to_string() }),
+            }
+            async_std::task::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
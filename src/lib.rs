@@ -0,0 +1,432 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use sqlx::Pool;
+use std::sync::Arc;
+use tera::Tera;
+use tide::http::cookies::SameSite;
+use tide::http::Method;
+use tide::prelude::*;
+use tide::{Endpoint, Error, Server};
+use tide_tera::prelude::*;
+use uuid::Uuid;
+
+pub mod api;
+pub mod cli;
+mod controllers;
+pub mod events;
+mod export;
+pub mod handlers;
+mod idempotency;
+pub mod import;
+pub mod jobs;
+pub mod logger;
+mod metrics;
+mod middlewares;
+mod negotiation;
+pub mod oauth;
+pub mod openapi;
+pub mod repository;
+pub mod seed;
+mod session_store;
+pub mod telemetry;
+pub mod webhooks;
+mod websocket;
+
+use controllers::auth;
+use controllers::dino;
+use controllers::views;
+use middlewares::metrics::MetricsMiddleware;
+use middlewares::request_log::RequestLogMiddleware;
+use middlewares::trace::TraceMiddleware;
+use middlewares::MatchedRoute;
+use oauth::AuthProvider;
+use repository::DinoRepository;
+use telemetry::traced;
+
+#[derive(Clone, Debug)]
+pub struct State {
+    db_pool: PgPool,
+    tera: Tera,
+    auth: Arc<dyn AuthProvider>,
+    metrics_token: Option<String>,
+    idempotency_ttl_secs: i64,
+    batch_max_size: usize,
+    webhook_allow_private_targets: bool,
+    events: events::Broadcaster,
+    dinos: Arc<dyn DinoRepository>,
+}
+
+// The settings of the app, `Config::from_env` reads them from the environment.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub tide_secret: String,
+    pub metrics_token: Option<String>,
+    // the metrics are served on this port rather than at `/metrics` when given
+    pub metrics_port: Option<String>,
+    pub idempotency_ttl_secs: i64,
+    pub batch_max_size: usize,
+    // lets webhooks deliver to loopback, private and link-local addresses
+    pub webhook_allow_private_targets: bool,
+    pub auth: Arc<dyn AuthProvider>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            tide_secret: std::env::var("TIDE_SECRET")
+                .expect("Please provide a TIDE_SECRET value of at least 32 bytes"),
+            metrics_token: std::env::var("METRICS_TOKEN").ok(),
+            metrics_port: std::env::var("METRICS_PORT").ok(),
+            idempotency_ttl_secs: std::env::var("IDEMPOTENCY_TTL_SECS")
+                .map(|ttl| ttl.parse().expect("IDEMPOTENCY_TTL_SECS must be a number"))
+                .unwrap_or(24 * 60 * 60),
+            batch_max_size: std::env::var("BATCH_MAX_SIZE")
+                .map(|size| size.parse().expect("BATCH_MAX_SIZE must be a number"))
+                .unwrap_or(1000),
+            webhook_allow_private_targets: allow_private_targets(),
+            auth: Arc::new(oauth::GoogleAuthProvider::from_env().unwrap()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Dino {
+    pub id: Uuid,
+    #[schemars(length(max = 255))]
+    pub name: String,
+    pub weight: i32,
+    #[schemars(length(max = 255))]
+    pub diet: String,
+    pub user_id: Option<String>,
+}
+
+// A dino in the trash
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct TrashedDino {
+    pub id: Uuid,
+    pub name: String,
+    pub weight: i32,
+    pub diet: String,
+    pub user_id: Option<String>,
+    pub deleted_at: chrono::DateTime<chrono::Utc>,
+}
+
+// The body of a create request, the id is generated by the server when missing.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct NewDino {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    #[schemars(length(max = 255))]
+    pub name: String,
+    pub weight: i32,
+    #[schemars(length(max = 255))]
+    pub diet: String,
+}
+
+// The fields of a dino that a partial update changes, `None` means unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct DinoChanges {
+    pub name: Option<String>,
+    pub weight: Option<i32>,
+    pub diet: Option<String>,
+    pub user_id: Option<Option<String>>,
+}
+
+impl DinoChanges {
+    pub fn between(current: &Dino, updated: &Dino) -> Self {
+        fn changed<T: Clone + PartialEq>(current: &T, updated: &T) -> Option<T> {
+            if current == updated {
+                None
+            } else {
+                Some(updated.clone())
+            }
+        }
+
+        DinoChanges {
+            name: changed(&current.name, &updated.name),
+            weight: changed(&current.weight, &updated.weight),
+            diet: changed(&current.diet, &updated.diet),
+            user_id: changed(&current.user_id, &updated.user_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct BatchRequest {
+    // all or nothing by default, `false` applies each operation on its own
+    #[serde(default = "BatchRequest::default_atomic")]
+    pub atomic: bool,
+    pub operations: Vec<BatchOperation>,
+}
+
+impl BatchRequest {
+    fn default_atomic() -> bool {
+        true
+    }
+}
+
+// An operation of a batch, an update keeps the owner of the dino.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOperation {
+    Create { dino: NewDino },
+    Update { id: Uuid, dino: NewDino },
+    Delete { id: Uuid },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct BatchResult {
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dino: Option<Dino>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchResult {
+    pub fn ok(status: u16, dino: Option<Dino>) -> Self {
+        BatchResult {
+            status,
+            dino,
+            error: None,
+        }
+    }
+
+    pub fn error(status: u16, error: &str) -> Self {
+        BatchResult {
+            status,
+            dino: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct BatchResponse {
+    pub committed: bool,
+    pub results: Vec<BatchResult>,
+}
+
+// #[derive(Debug, Clone, Deserialize, Serialize)]
+// pub struct Dinos {
+//     dinos: Vec<Dino>,
+// }
+
+// The Postgres pool of the app, `repository::sqlite::make_db_pool` opens the SQLite file of a
+// standalone one.
+pub async fn make_db_pool(db_url: &str) -> PgPool {
+    Pool::connect(db_url).await.unwrap()
+}
+
+pub async fn server(db_pool: PgPool, config: Config) -> Server<State> {
+    let dinos = Arc::new(repository::PgDinoRepository::new(db_pool.clone()));
+    let app = app(db_pool.clone(), dinos, config, true).await;
+
+    // the changes made by any instance are pushed to the clients of this one
+    async_std::task::spawn(events::listen(db_pool, app.state().events.clone()));
+
+    app
+}
+
+// The app without Postgres, keeping its dinos in `dinos` and its sessions in memory. Only the
+// routes of `api::v1::REPOSITORY_ROUTES` and the pages listing, creating and restoring dinos are
+// served: logging in, the history, the revisions, the batches, the imports, the live updates, the
+// webhooks and the metrics need Postgres.
+pub async fn standalone_server(dinos: Arc<dyn DinoRepository>, config: Config) -> Server<State> {
+    // never connected, none of the routes served uses it
+    let db_pool = sqlx::postgres::PgPoolOptions::new()
+        .connect_lazy("postgres://localhost/unavailable")
+        .expect("a valid Postgres url");
+    app(db_pool, dinos, config, false).await
+}
+
+fn sessions<S: tide::sessions::SessionStore>(
+    store: S,
+    secret: &str,
+) -> tide::sessions::SessionMiddleware<S> {
+    tide::sessions::SessionMiddleware::new(store, secret.as_bytes())
+        .with_same_site_policy(SameSite::Lax)
+        // only the sessions holding something are written to the store
+        .without_save_unchanged()
+}
+
+// The app keeping its dinos in `dinos`, with the routes needing Postgres when `postgres` is set.
+async fn app(
+    db_pool: PgPool,
+    dinos: Arc<dyn DinoRepository>,
+    config: Config,
+    postgres: bool,
+) -> Server<State> {
+    let mut tera = Tera::new("templates/**/*").expect("Error parsing templates directory");
+    tera.autoescape_on(vec!["html"]);
+
+    // fed by the Postgres notifications when the dinos are kept there
+    let events = events::Broadcaster::default();
+
+    let state = State {
+        db_pool: db_pool.clone(),
+        tera,
+        auth: config.auth,
+        metrics_token: config.metrics_token,
+        idempotency_ttl_secs: config.idempotency_ttl_secs,
+        batch_max_size: config.batch_max_size,
+        webhook_allow_private_targets: config.webhook_allow_private_targets,
+        events,
+        dinos,
+    };
+
+    metrics::register();
+
+    let mut app = tide::with_state(state);
+
+    app.with(TraceMiddleware::new());
+    app.with(MetricsMiddleware::new());
+    // sessions are kept in the database, shared by every instance
+    if postgres {
+        app.with(sessions(
+            session_store::PgSessionStore::new(db_pool),
+            &config.tide_secret,
+        ));
+    } else {
+        app.with(sessions(
+            tide::sessions::MemoryStore::new(),
+            &config.tide_secret,
+        ));
+    }
+    app.with(RequestLogMiddleware::new());
+
+    // views
+    route(&mut app, "/").get(traced("views::index", views::index));
+    route(&mut app, "/dinos/new").get(traced("views::new", views::new));
+    route(&mut app, "/dinos/trash").get(traced("views::trash", views::trash));
+    if postgres {
+        route(&mut app, "/dinos/import").get(traced("views::import", views::import));
+        route(&mut app, "/dinos/:id/edit").get(traced("views::edit", views::edit));
+
+        // auth
+        route(&mut app, "/auth/google").get(traced("auth::auth_google", auth::auth_google));
+        route(&mut app, "/auth/google/authorized").get(traced(
+            "auth::auth_google_authorized",
+            auth::auth_google_authorized,
+        ));
+
+        route(&mut app, "/logout").get(traced("auth::logout", auth::logout));
+    }
+
+    // api, each version is mounted under its own prefix
+    let v1_routes = || {
+        api::v1::routes()
+            .into_iter()
+            .filter(move |(method, path, _)| {
+                postgres || api::v1::REPOSITORY_ROUTES.contains(&(*method, *path))
+            })
+    };
+    api::mount(&mut app, api::v1::PREFIX, v1_routes().collect());
+
+    // the unversioned paths are kept as deprecated aliases of v1
+    let legacy_routes = v1_routes()
+        .filter(|(method, path, _)| api::v1::LEGACY_ROUTES.contains(&(*method, *path)))
+        .collect();
+    api::mount_deprecated(&mut app, api::v1::PREFIX, legacy_routes);
+
+    // api docs
+    route(&mut app, "/openapi.json").get(traced("docs::openapi", controllers::docs::openapi));
+    route(&mut app, "/docs").get(traced("docs::index", controllers::docs::index));
+
+    route(&mut app, "/public")
+        .serve_dir("./public/")
+        .expect("Invalid static file directory");
+
+    // metrics, unless they are served from the admin port
+    if postgres && config.metrics_port.is_none() {
+        route(&mut app, "/metrics").get(controllers::metrics::show);
+    }
+
+    app
+}
+
+// A route of the app, labelled with its pattern in the metrics and traces.
+fn route<'a>(app: &'a mut Server<State>, path: &str) -> tide::Route<'a, State> {
+    let mut route = app.at(path);
+    route.with(MatchedRoute::new(path));
+    route
+}
+
+pub fn metrics_server(state: State) -> Server<State> {
+    let mut app = tide::with_state(state);
+    app.at("/metrics").get(controllers::metrics::show);
+    app
+}
+
+pub fn workers(default: usize) -> usize {
+    std::env::var("WORKERS")
+        .map(|n| n.parse().expect("WORKERS must be a number"))
+        .unwrap_or(default)
+}
+
+fn allow_private_targets() -> bool {
+    std::env::var("WEBHOOK_ALLOW_PRIVATE_TARGETS")
+        .map(|allow| {
+            allow
+                .parse()
+                .expect("WEBHOOK_ALLOW_PRIVATE_TARGETS must be true or false")
+        })
+        .unwrap_or(false)
+}
+
+// The background jobs and their schedules.
+pub fn worker(db_pool: PgPool) -> jobs::Worker {
+    let schedule = |var: &str, default: &str| -> jobs::Schedule {
+        let schedule = std::env::var(var).unwrap_or_else(|_| default.to_string());
+        schedule
+            .parse()
+            .unwrap_or_else(|e| panic!("{} is invalid: {}", var, e))
+    };
+
+    // empty the trash of the dinos deleted for longer than the retention
+    let retention_secs = std::env::var("TRASH_RETENTION_SECS")
+        .map(|secs| secs.parse().expect("TRASH_RETENTION_SECS must be a number"))
+        .unwrap_or(30 * 24 * 60 * 60);
+
+    // send the webhook deliveries of the outbox, retrying the failed ones with a backoff
+    let retry = webhooks::RetryPolicy {
+        base_secs: std::env::var("WEBHOOK_RETRY_BASE_SECS")
+            .map(|secs| {
+                secs.parse()
+                    .expect("WEBHOOK_RETRY_BASE_SECS must be a number")
+            })
+            .unwrap_or(30),
+        max_secs: 6 * 60 * 60,
+        max_attempts: std::env::var("WEBHOOK_MAX_ATTEMPTS")
+            .map(|n| n.parse().expect("WEBHOOK_MAX_ATTEMPTS must be a number"))
+            .unwrap_or(8),
+    };
+
+    jobs::Worker::new(db_pool)
+        .recurring(
+            "purge_trash",
+            schedule("TRASH_PURGE_SCHEDULE", "@hourly"),
+            jobs::tasks::PurgeTrash { retention_secs },
+        )
+        .recurring(
+            "deliver_webhooks",
+            schedule("WEBHOOK_SCHEDULE", "@every 5s"),
+            jobs::tasks::DeliverWebhooks {
+                retry,
+                allow_private_targets: allow_private_targets(),
+            },
+        )
+        .recurring(
+            "purge_sessions",
+            schedule("SESSIONS_PURGE_SCHEDULE", "@hourly"),
+            jobs::tasks::PurgeSessions {},
+        )
+        .recurring(
+            "purge_jobs",
+            schedule("JOBS_PURGE_SCHEDULE", "0 4 * * *"),
+            jobs::tasks::PurgeJobs {
+                retention_secs: 7 * 24 * 60 * 60,
+            },
+        )
+}
@@ -0,0 +1,178 @@
+use async_std::task_local;
+use log::{kv, Level, LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::io::Write;
+use std::str::FromStr;
+
+// keys whose values must never reach the logs
+static REDACTED_KEYS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "session",
+    "user_name",
+    "token",
+    "secret",
+    "password",
+];
+
+// tide's own access log is replaced by `middlewares::request_log`
+static SKIPPED_TARGETS: &[&str] = &["tide::log::middleware"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Pretty,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "pretty" => Ok(Format::Pretty),
+            _ => Err(format!("unknown log format `{}`", s)),
+        }
+    }
+}
+
+// per request fields, attached to every record logged while serving it
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub user_id: Option<String>,
+}
+
+task_local! {
+    static CONTEXT: RefCell<Option<RequestContext>> = RefCell::new(None);
+}
+
+pub fn set_context(context: Option<RequestContext>) {
+    let _ = CONTEXT.try_with(|c| *c.borrow_mut() = context);
+}
+
+fn context() -> Option<RequestContext> {
+    CONTEXT.try_with(|c| c.borrow().clone()).ok().flatten()
+}
+
+#[derive(Debug)]
+struct Logger {
+    format: Format,
+}
+
+// Start logging, configured by `LOG_LEVEL` (default `info`)
+// and `LOG_FORMAT` (`json` or `pretty`, default `json`).
+pub fn start() {
+    let level = std::env::var("LOG_LEVEL")
+        .ok()
+        .map(|level| LevelFilter::from_str(&level).expect("invalid LOG_LEVEL value"))
+        .unwrap_or(LevelFilter::Info);
+    let format = std::env::var("LOG_FORMAT")
+        .ok()
+        .map(|format| Format::from_str(&format).expect("invalid LOG_FORMAT value"))
+        .unwrap_or(Format::Json);
+
+    log::set_boxed_logger(Box::new(Logger { format })).expect("Could not start logging");
+    log::set_max_level(level);
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+            && !SKIPPED_TARGETS
+                .iter()
+                .any(|target| metadata.target().starts_with(target))
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let fields = fields(record);
+        let stdout = std::io::stdout();
+        let mut handle = stdout.lock();
+        let _ = match self.format {
+            Format::Json => writeln!(handle, "{}", Value::Object(fields)),
+            Format::Pretty => {
+                let pairs: Vec<String> = fields
+                    .iter()
+                    .filter(|(k, _)| !["time", "level", "msg"].contains(&k.as_str()))
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect();
+                writeln!(
+                    handle,
+                    "{} {:<5} {} {}",
+                    fields["time"].as_str().unwrap_or_default(),
+                    record.level(),
+                    record.args(),
+                    pairs.join(" ")
+                )
+            }
+        };
+    }
+
+    fn flush(&self) {}
+}
+
+fn fields(record: &Record<'_>) -> Map<String, Value> {
+    let mut fields = Map::new();
+    fields.insert("time".into(), chrono::Utc::now().to_rfc3339().into());
+    fields.insert("level".into(), level_name(record.level()).into());
+    fields.insert("target".into(), record.target().into());
+    fields.insert("msg".into(), record.args().to_string().into());
+
+    if let Some(context) = context() {
+        fields.insert("request_id".into(), context.request_id.into());
+        if let Some(user_id) = context.user_id {
+            fields.insert("user_id".into(), user_id.into());
+        }
+    }
+
+    struct Visitor<'a>(&'a mut Map<String, Value>);
+
+    impl<'kvs, 'a> kv::VisitSource<'kvs> for Visitor<'a> {
+        fn visit_pair(
+            &mut self,
+            key: kv::Key<'kvs>,
+            val: kv::Value<'kvs>,
+        ) -> Result<(), kv::Error> {
+            self.0.insert(key.to_string(), redact(key.as_str(), &val));
+            Ok(())
+        }
+    }
+
+    let _ = record.key_values().visit(&mut Visitor(&mut fields));
+    fields
+}
+
+fn redact(key: &str, val: &kv::Value<'_>) -> Value {
+    let key = key.to_lowercase();
+    if REDACTED_KEYS.iter().any(|redacted| key.contains(redacted)) {
+        return "[REDACTED]".into();
+    }
+
+    if let Some(v) = val.to_bool() {
+        v.into()
+    } else if let Some(v) = val.to_i64() {
+        v.into()
+    } else if let Some(v) = val.to_u64() {
+        v.into()
+    } else if let Some(v) = val.to_f64() {
+        v.into()
+    } else {
+        val.to_string().into()
+    }
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warn",
+        Level::Info => "info",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
@@ -1,144 +1,161 @@
-use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use sqlx::Pool;
-use tera::Tera;
-use tide::http::cookies::SameSite;
 use tide::prelude::*;
-use tide::{Error, Server};
-use tide_tera::prelude::*;
-use uuid::Uuid;
 
-mod controllers;
-mod handlers;
+#[cfg(feature = "sqlite")]
+use std::sync::Arc;
+#[cfg(feature = "sqlite")]
+use tide_basic_crud::repository::{self, DinoRepository};
+#[cfg(feature = "sqlite")]
+use tide_basic_crud::standalone_server;
+use tide_basic_crud::{cli, logger, telemetry};
+use tide_basic_crud::{make_db_pool, metrics_server, server, worker, workers, Config};
 
-use controllers::auth;
-use controllers::dino;
-use controllers::views;
+#[async_std::main]
+async fn main() {
+    dotenv::dotenv().ok();
 
-// OAuth deps and const
-use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
+    let cli = match cli::Cli::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if cli.command == cli::Command::Help {
+        println!("{}", cli::USAGE);
+        return;
+    }
 
-static AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
-static TOKEN_URL: &str = "https://www.googleapis.com/oauth2/v3/token";
+    let db_url = std::env::var("DATABASE_URL")
+        .expect("Missing `DATABASE_URL` env variable, needed for running the server");
 
-#[derive(Clone, Debug)]
-pub struct State {
-    db_pool: PgPool,
-    tera: Tera,
-    oauth_google_client: BasicClient,
-}
+    // `DATABASE_URL=sqlite:dinos.db` keeps the dinos in an SQLite file, for demos
+    #[cfg(feature = "sqlite")]
+    if db_url.starts_with("sqlite:") {
+        return serve_sqlite(&db_url, cli).await;
+    }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Dino {
-    id: Uuid,
-    name: String,
-    weight: i32,
-    diet: String,
-    user_id: Option<String>,
+    let db_pool = make_db_pool(&db_url).await;
+
+    match cli.command {
+        cli::Command::Serve => serve(Backend::Postgres(db_pool)).await,
+        // `tide-basic-crud worker` only runs the background jobs, the web instances can leave
+        // them to it with `WORKERS=0`
+        cli::Command::Worker => {
+            logger::start();
+            worker(db_pool).run(workers(1)).await;
+        }
+        // the admin commands don't log, their output on stdout is for scripts. `export` writes
+        // the dinos there, so what it did goes to stderr
+        command => match cli::run(command.clone(), &db_pool).await {
+            Ok(output) if matches!(command, cli::Command::Export { .. }) => match cli.json {
+                true => eprintln!("{}", output.json),
+                false => eprintln!("{}", output.text),
+            },
+            Ok(output) if cli.json => println!("{}", output.json),
+            Ok(output) => println!("{}", output.text),
+            Err(e) if cli.json => {
+                println!(
+                    "{}",
+                    json!({ "error": e.to_string(), "status": e.status() })
+                );
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        },
+    }
 }
 
-// #[derive(Debug, Clone, Deserialize, Serialize)]
-// pub struct Dinos {
-//     dinos: Vec<Dino>,
-// }
+// Where the app keeps its data.
+enum Backend {
+    Postgres(PgPool),
+    // only the dinos, see `standalone_server`
+    #[cfg(feature = "sqlite")]
+    Standalone(Arc<dyn DinoRepository>),
+}
 
-#[async_std::main]
-async fn main() {
-    dotenv::dotenv().ok();
+async fn serve(backend: Backend) {
+    logger::start();
 
-    tide::log::start();
-    let db_url = std::env::var("DATABASE_URL")
-        .expect("Missing `DATABASE_URL` env variable, needed for running the server");
+    // export traces when an OTLP collector is configured
+    let tracer_provider = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .map(|endpoint| {
+            telemetry::init(&format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+                .expect("can't start the OTLP exporter")
+        });
     let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let db_pool = make_db_pool(&db_url).await;
+    let config = Config::from_env();
+
+    // the jobs and the metrics work on the Postgres tables
+    let (app, metrics_port) = match backend {
+        Backend::Postgres(db_pool) => {
+            let concurrency = workers(1);
+            if concurrency > 0 {
+                async_std::task::spawn(worker(db_pool.clone()).run(concurrency));
+            }
+            let app = server(db_pool, config.clone()).await;
+            (app, config.metrics_port)
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Standalone(dinos) => (standalone_server(dinos, config).await, None),
+    };
+
+    // serve the metrics on a separate admin port when one is configured
+    if let Some(metrics_port) = metrics_port {
+        let admin = metrics_server(app.state().clone());
+        async_std::task::spawn(async move {
+            admin
+                .listen(format!("0.0.0.0:{}", metrics_port))
+                .await
+                .expect("can't bind the metrics port");
+        });
+    }
 
-    let app = server(db_pool).await;
     let mut listener = app
         .bind(format!("0.0.0.0:{}", port))
         .await
         .expect("can't bind the port");
 
     for info in listener.info().iter() {
-        println!("Server listening on {}", info);
+        tide::log::info!("Server listening on {}", info);
     }
     listener.accept().await.unwrap();
-}
-
-pub async fn make_db_pool(db_url: &str) -> PgPool {
-    Pool::connect(db_url).await.unwrap()
-}
-
-fn make_oauth_google_client() -> tide::Result<BasicClient> {
-    let client = BasicClient::new(
-        ClientId::new(
-            std::env::var("OAUTH_GOOGLE_CLIENT_ID")
-                .expect("missing env var OAUTH_GOOGLE_CLIENT_ID"),
-        ),
-        Some(ClientSecret::new(
-            std::env::var("OAUTH_GOOGLE_CLIENT_SECRET")
-                .expect("missing env var OAUTH_GOOGLE_CLIENT_SECRET"),
-        )),
-        AuthUrl::new(AUTH_URL.to_string())?,
-        Some(TokenUrl::new(TOKEN_URL.to_string())?),
-    )
-    .set_redirect_uri(RedirectUrl::new(
-        std::env::var("OAUTH_GOOGLE_REDIRECT_URL")
-            .expect("missing env var OAUTH_GOOGLE_REDIRECT_URL"),
-    )?);
 
-    Ok(client)
+    if let Some(provider) = tracer_provider {
+        provider.shutdown().ok();
+    }
 }
 
-async fn server(db_pool: PgPool) -> Server<State> {
-    let mut tera = Tera::new("templates/**/*").expect("Error parsing templates directory");
-    tera.autoescape_on(vec!["html"]);
-
-    let oauth_google_client = make_oauth_google_client().unwrap();
-
-    let state = State {
-        db_pool,
-        tera,
-        oauth_google_client,
-    };
-
-    let mut app = tide::with_state(state);
-
-    app.with(
-        tide::sessions::SessionMiddleware::new(
-            tide::sessions::MemoryStore::new(),
-            std::env::var("TIDE_SECRET")
-                .expect("Please provide a TIDE_SECRET value of at least 32 bytes")
-                .as_bytes(),
-        )
-        .with_same_site_policy(SameSite::Lax),
-    );
-
-    // views
-    app.at("/").get(views::index);
-    app.at("/dinos/new").get(views::new);
-    app.at("/dinos/:id/edit").get(views::edit);
-
-    // auth
-    app.at("/auth/google")
-        .get(auth::auth_google)
-        .at("/authorized")
-        .get(auth::auth_google_authorized);
-
-    app.at("/logout").get(auth::logout);
-
-    // api
-    app.at("/dinos").get(dino::list).post(dino::create);
-
-    app.at("/dinos/:id")
-        .get(dino::get)
-        .put(dino::update)
-        .delete(dino::delete);
-
-    app.at("/public")
-        .serve_dir("./public/")
-        .expect("Invalid static file directory");
-
-    app
+// Only the dinos are kept in SQLite, the app is a standalone one: the routes needing Postgres
+// aren't served and there are no background jobs.
+#[cfg(feature = "sqlite")]
+async fn serve_sqlite(db_url: &str, cli: cli::Cli) {
+    let sqlite_pool = repository::sqlite::make_db_pool(db_url)
+        .await
+        .expect("can't open the SQLite database");
+
+    match cli.command {
+        cli::Command::Serve => {
+            let dinos = Arc::new(repository::SqliteDinoRepository::new(sqlite_pool));
+            serve(Backend::Standalone(dinos)).await
+        }
+        // done when the pool was made
+        cli::Command::Migrate => {
+            let output = cli::migrated(&sqlx::migrate!("./migrations/sqlite"));
+            match cli.json {
+                true => println!("{}", output.json),
+                false => println!("{}", output.text),
+            }
+        }
+        _ => {
+            eprintln!("error: only `serve` and `migrate` work with SQLite");
+            std::process::exit(1);
+        }
+    }
 }
 
 // #[async_std::test]
@@ -170,423 +187,3 @@ async fn server(db_pool: PgPool) -> Server<State> {
 //     assert_eq!(200, res.status());
 //     Ok(())
 // }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use lazy_static::lazy_static;
-    use sqlx::query;
-
-    lazy_static! {
-        static ref DB_URL: String =
-            std::env::var("DATABASE_URL").expect("missing env var DATABASE_URL");
-    }
-
-    async fn clear_dinos() -> Result<(), Box<dyn std::error::Error>> {
-        let db_pool = make_db_pool(&DB_URL).await;
-
-        sqlx::query("DELETE FROM dinos").execute(&db_pool).await?;
-        Ok(())
-    }
-
-    #[test]
-    fn clear() {
-        dotenv::dotenv().ok();
-        async_std::task::block_on(async {
-            clear_dinos().await.unwrap();
-            ()
-        })
-    }
-
-    #[async_std::test]
-    async fn list_dinos() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        let db_pool = make_db_pool(&DB_URL).await;
-        let app = server(db_pool).await;
-
-        let res = surf::Client::with_http_client(app)
-            .get("https://example.com/dinos")
-            .await?;
-
-        assert_eq!(200, res.status());
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn create_dino() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        use assert_json_diff::assert_json_eq;
-
-        let dino = Dino {
-            id: Uuid::new_v4(),
-            name: String::from("test"),
-            weight: 50,
-            diet: String::from("carnivorous"),
-            user_id: None,
-        };
-
-        let db_pool = make_db_pool(&DB_URL).await;
-        let app = server(db_pool).await;
-
-        let mut res = surf::Client::with_http_client(app)
-            .post("https://example.com/dinos")
-            .body(serde_json::to_string(&dino)?)
-            .await?;
-
-        assert_eq!(201, res.status());
-
-        let d: Dino = res.body_json().await?;
-        assert_json_eq!(dino, d);
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn create_dino_with_existing_key() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        let dino = Dino {
-            id: Uuid::new_v4(),
-            name: String::from("test_get"),
-            weight: 500,
-            diet: String::from("carnivorous"),
-            user_id: None,
-        };
-
-        let db_pool = make_db_pool(&DB_URL).await;
-
-        // create the dino
-        query!(
-            r#"
-            INSERT INTO dinos (id, name, weight, diet) VALUES
-            ($1, $2, $3, $4) returning id, name, weight, diet
-            "#,
-            dino.id,
-            dino.name,
-            dino.weight,
-            dino.diet
-        )
-        .fetch_one(&db_pool)
-        .await?;
-
-        // start the server
-        let app = server(db_pool).await;
-
-        let res = surf::Client::with_http_client(app)
-            .post("https://example.com/dinos")
-            .body(serde_json::to_string(&dino)?)
-            .await?;
-
-        assert_eq!(409, res.status());
-
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn get_dino() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        use assert_json_diff::assert_json_eq;
-
-        let dino = Dino {
-            id: Uuid::new_v4(),
-            name: String::from("test_get"),
-            weight: 500,
-            diet: String::from("carnivorous"),
-            user_id: None,
-        };
-
-        let db_pool = make_db_pool(&DB_URL).await;
-
-        // create the dino for get
-        query!(
-            r#"
-            INSERT INTO dinos (id, name, weight, diet) VALUES
-            ($1, $2, $3, $4) returning id, name, weight, diet
-            "#,
-            dino.id,
-            dino.name,
-            dino.weight,
-            dino.diet
-        )
-        .fetch_one(&db_pool)
-        .await?;
-
-        // start the server
-        let app = server(db_pool).await;
-
-        let mut res = surf::Client::with_http_client(app)
-            .get(format!("https://example.com/dinos/{}", &dino.id))
-            .await?;
-
-        assert_eq!(200, res.status());
-
-        let d: Dino = res.body_json().await?;
-        assert_json_eq!(dino, d);
-
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn get_dino_non_existing_key() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        // start the server
-        let db_pool = make_db_pool(&DB_URL).await;
-        let app = server(db_pool).await;
-
-        let res = surf::Client::with_http_client(app)
-            .get(format!("https://example.com/dinos/{}", &Uuid::new_v4()))
-            .await?;
-
-        assert_eq!(404, res.status());
-
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn update_dino() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        use assert_json_diff::assert_json_eq;
-
-        let mut dino = Dino {
-            id: Uuid::new_v4(),
-            name: String::from("test_update"),
-            weight: 500,
-            diet: String::from("carnivorous"),
-            user_id: None,
-        };
-
-        let db_pool = make_db_pool(&DB_URL).await;
-
-        // create the dino for update
-        query!(
-            r#"
-            INSERT INTO dinos (id, name, weight, diet) VALUES
-            ($1, $2, $3, $4) returning id, name, weight, diet
-            "#,
-            dino.id,
-            dino.name,
-            dino.weight,
-            dino.diet
-        )
-        .fetch_one(&db_pool)
-        .await?;
-
-        // change the dino
-        dino.name = String::from("updated from test");
-
-        // start the server
-        let app = server(db_pool).await;
-
-        let mut res = surf::Client::with_http_client(app)
-            .put(format!("https://example.com/dinos/{}", &dino.id))
-            .body(serde_json::to_string(&dino)?)
-            .await?;
-
-        assert_eq!(200, res.status());
-
-        let d: Dino = res.body_json().await?;
-        assert_json_eq!(dino, d);
-
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn updatet_dino_non_existing_key() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        let dino = Dino {
-            id: Uuid::new_v4(),
-            name: String::from("test_update"),
-            weight: 500,
-            diet: String::from("carnivorous"),
-            user_id: None,
-        };
-
-        // start the server
-        let db_pool = make_db_pool(&DB_URL).await;
-        let app = server(db_pool).await;
-
-        let res = surf::Client::with_http_client(app)
-            .put(format!("https://example.com/dinos/{}", &dino.id))
-            .body(serde_json::to_string(&dino)?)
-            .await?;
-
-        assert_eq!(404, res.status());
-
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn updatet_dino_create_by_another_user_should_reject_with_401() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-
-        let mut dino = Dino {
-            id: Uuid::new_v4(),
-            name: String::from("test_update"),
-            weight: 500,
-            diet: String::from("carnivorous"),
-            user_id: Some(String::from("123")),
-        };
-
-        let db_pool = make_db_pool(&DB_URL).await;
-
-        // create the dino for update
-        query!(
-            r#"
-            INSERT INTO dinos (id, name, weight, diet, user_id) VALUES
-            ($1, $2, $3, $4, $5) returning id
-            "#,
-            dino.id,
-            dino.name,
-            dino.weight,
-            dino.diet,
-            dino.user_id
-        )
-        .fetch_one(&db_pool)
-        .await?;
-
-        // change the dino
-        dino.name = String::from("updated from test");
-
-        // start the server
-        let app = server(db_pool).await;
-
-        let res = surf::Client::with_http_client(app)
-            .put(format!("https://example.com/dinos/{}", &dino.id))
-            .body(serde_json::to_string(&dino)?)
-            .await?;
-
-        assert_eq!(401, res.status());
-
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn delete_dino() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        let dino = Dino {
-            id: Uuid::new_v4(),
-            name: String::from("test_delete"),
-            weight: 500,
-            diet: String::from("carnivorous"),
-            user_id: None,
-        };
-
-        let db_pool = make_db_pool(&DB_URL).await;
-
-        // create the dino for delete
-        query!(
-            r#"
-            INSERT INTO dinos (id, name, weight, diet) VALUES
-            ($1, $2, $3, $4) returning id, name, weight, diet
-            "#,
-            dino.id,
-            dino.name,
-            dino.weight,
-            dino.diet
-        )
-        .fetch_one(&db_pool)
-        .await?;
-
-        // start the server
-        let app = server(db_pool).await;
-
-        let res = surf::Client::with_http_client(app)
-            .delete(format!("https://example.com/dinos/{}", &dino.id))
-            .await?;
-
-        assert_eq!(204, res.status());
-
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn delete_dino_non_existing_key() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-        // clear_dinos()
-        //     .await
-        //     .expect("Failed to clear the dinos table");
-
-        // start the server
-        let db_pool = make_db_pool(&DB_URL).await;
-        let app = server(db_pool).await;
-
-        let res = surf::Client::with_http_client(app)
-            .delete(format!("https://example.com/dinos/{}", &Uuid::new_v4()))
-            .await?;
-
-        assert_eq!(404, res.status());
-
-        Ok(())
-    }
-
-    #[async_std::test]
-    async fn delete_dino_create_by_another_user_should_reject_with_401() -> tide::Result<()> {
-        dotenv::dotenv().ok();
-
-        let dino = Dino {
-            id: Uuid::new_v4(),
-            name: String::from("test_delete"),
-            weight: 500,
-            diet: String::from("carnivorous"),
-            user_id: Some(String::from("123")),
-        };
-
-        let db_pool = make_db_pool(&DB_URL).await;
-
-        // create the dino for delete
-        query!(
-            r#"
-            INSERT INTO dinos (id, name, weight, diet, user_id) VALUES
-            ($1, $2, $3, $4, $5) returning id
-            "#,
-            dino.id,
-            dino.name,
-            dino.weight,
-            dino.diet,
-            dino.user_id
-        )
-        .fetch_one(&db_pool)
-        .await?;
-
-        // start the server
-        let app = server(db_pool).await;
-
-        let res = surf::Client::with_http_client(app)
-            .delete(format!("https://example.com/dinos/{}", &dino.id))
-            .await?;
-
-        assert_eq!(401, res.status());
-
-        Ok(())
-    }
-}
@@ -0,0 +1,90 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use sqlx::PgPool;
+
+lazy_static! {
+    pub static ref REGISTRY: Registry = Registry::new();
+    pub static ref HTTP_REQUESTS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("http_requests_total", "Number of HTTP requests"),
+        &["method", "route", "status"]
+    )
+    .expect("metric can be created");
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_duration_seconds",
+            "HTTP request latency in seconds"
+        ),
+        &["method", "route", "status"]
+    )
+    .expect("metric can be created");
+    pub static ref DB_QUERY_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("db_query_duration_seconds", "SQL query latency in seconds"),
+        &["query"]
+    )
+    .expect("metric can be created");
+    pub static ref DB_POOL_CONNECTIONS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("db_pool_connections", "Connections in the database pool"),
+        &["state"]
+    )
+    .expect("metric can be created");
+    pub static ref SESSIONS_ACTIVE: IntGauge =
+        IntGauge::new("sessions_active", "Number of stored sessions")
+            .expect("metric can be created");
+    pub static ref OAUTH_LOGINS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("oauth_logins_total", "Number of OAuth logins"),
+        &["provider", "result"]
+    )
+    .expect("metric can be created");
+}
+
+pub fn register() {
+    // registering twice is an error, so only the first call does the work
+    lazy_static! {
+        static ref REGISTERED: () = {
+            REGISTRY
+                .register(Box::new(HTTP_REQUESTS_TOTAL.clone()))
+                .expect("collector can be registered");
+            REGISTRY
+                .register(Box::new(HTTP_REQUEST_DURATION.clone()))
+                .expect("collector can be registered");
+            REGISTRY
+                .register(Box::new(DB_QUERY_DURATION.clone()))
+                .expect("collector can be registered");
+            REGISTRY
+                .register(Box::new(DB_POOL_CONNECTIONS.clone()))
+                .expect("collector can be registered");
+            REGISTRY
+                .register(Box::new(SESSIONS_ACTIVE.clone()))
+                .expect("collector can be registered");
+            REGISTRY
+                .register(Box::new(OAUTH_LOGINS_TOTAL.clone()))
+                .expect("collector can be registered");
+        };
+    }
+    lazy_static::initialize(&REGISTERED);
+}
+
+// gauges that are cheaper to sample at scrape time than to keep updated
+pub async fn sample(db_pool: &PgPool) {
+    let size = db_pool.size() as i64;
+    let idle = db_pool.num_idle() as i64;
+    DB_POOL_CONNECTIONS.with_label_values(&["size"]).set(size);
+    DB_POOL_CONNECTIONS.with_label_values(&["idle"]).set(idle);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set(size - idle);
+
+    match crate::handlers::session::count(db_pool).await {
+        Ok(count) => SESSIONS_ACTIVE.set(count),
+        Err(e) => tide::log::warn!("can't count the sessions", { error: e.to_string() }),
+    }
+}
+
+pub fn render() -> tide::Result<String> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
@@ -0,0 +1,32 @@
+use tide::{Middleware, Next, Request};
+
+// the date after which the unversioned api aliases may be removed
+pub static SUNSET: &str = "Thu, 31 Dec 2026 23:59:59 GMT";
+
+// Flags responses of a deprecated route, pointing to its successor (RFC 8594).
+#[derive(Debug, Clone)]
+pub struct Deprecated {
+    successor: &'static str,
+}
+
+impl Deprecated {
+    pub fn new(successor: &'static str) -> Self {
+        Self { successor }
+    }
+}
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> Middleware<S> for Deprecated {
+    async fn handle(&self, req: Request<S>, next: Next<'_, S>) -> tide::Result {
+        let successor = format!("{}{}", self.successor, req.url().path());
+
+        let mut res = next.run(req).await;
+        res.insert_header("Deprecation", "true");
+        res.insert_header("Sunset", SUNSET);
+        res.insert_header(
+            "Link",
+            format!("<{}>; rel=\"successor-version\"", successor),
+        );
+        Ok(res)
+    }
+}
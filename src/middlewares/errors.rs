@@ -0,0 +1,37 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tide::{Body, Middleware, Next, Request};
+
+// The body of the api errors.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ApiError {
+    pub error: String,
+    pub status: u16,
+}
+
+// Gives the error responses of the api a JSON body, unless the endpoint already wrote one.
+#[derive(Debug, Clone, Default)]
+pub struct JsonErrors;
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> Middleware<S> for JsonErrors {
+    async fn handle(&self, req: Request<S>, next: Next<'_, S>) -> tide::Result {
+        let mut res = next.run(req).await;
+        let status = res.status();
+        if !(status.is_client_error() || status.is_server_error()) || res.is_empty() == Some(false)
+        {
+            return Ok(res);
+        }
+
+        // the server errors may carry database details, only the reason is given back
+        let error = match res.error() {
+            Some(e) if status.is_client_error() => e.to_string(),
+            _ => status.canonical_reason().to_string(),
+        };
+        res.set_body(Body::from_json(&ApiError {
+            error,
+            status: status.into(),
+        })?);
+        Ok(res)
+    }
+}
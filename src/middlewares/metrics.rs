@@ -0,0 +1,34 @@
+use std::time::Instant;
+use tide::{Middleware, Next, Request};
+
+use super::route_label;
+use crate::metrics::{HTTP_REQUESTS_TOTAL, HTTP_REQUEST_DURATION};
+
+#[derive(Debug, Default, Clone)]
+pub struct MetricsMiddleware;
+
+impl MetricsMiddleware {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> Middleware<S> for MetricsMiddleware {
+    async fn handle(&self, req: Request<S>, next: Next<'_, S>) -> tide::Result {
+        let start = Instant::now();
+        let method = req.method().to_string();
+
+        let res = next.run(req).await;
+
+        let route = route_label(&res);
+        let status = u16::from(res.status()).to_string();
+        let labels = [method.as_str(), route.as_str(), status.as_str()];
+        HTTP_REQUESTS_TOTAL.with_label_values(&labels).inc();
+        HTTP_REQUEST_DURATION
+            .with_label_values(&labels)
+            .observe(start.elapsed().as_secs_f64());
+
+        Ok(res)
+    }
+}
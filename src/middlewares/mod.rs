@@ -0,0 +1,39 @@
+use super::*;
+
+use tide::{Middleware, Next, Request, Response};
+
+pub mod deprecation;
+pub mod errors;
+pub mod metrics;
+pub mod request_log;
+pub mod trace;
+
+// the label of the requests no route matched, whatever their path
+pub static UNMATCHED: &str = "unmatched";
+
+// The pattern a route was registered with, e.g. `/api/v1/dinos/:id`, put on its responses so
+// the metrics and traces are labelled with it: their label cardinality stays bounded by the routes.
+#[derive(Debug, Clone)]
+pub struct MatchedRoute(pub String);
+
+impl MatchedRoute {
+    pub fn new(pattern: &str) -> Self {
+        Self(pattern.to_string())
+    }
+}
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> Middleware<S> for MatchedRoute {
+    async fn handle(&self, req: Request<S>, next: Next<'_, S>) -> tide::Result {
+        let mut res = next.run(req).await;
+        res.insert_ext(self.clone());
+        Ok(res)
+    }
+}
+
+pub fn route_label(res: &Response) -> String {
+    match res.ext::<MatchedRoute>() {
+        Some(route) => route.0.clone(),
+        None => UNMATCHED.to_string(),
+    }
+}
@@ -0,0 +1,89 @@
+use super::*;
+
+use std::time::Instant;
+use tide::{Middleware, Next, Request};
+
+use super::route_label;
+use crate::logger::{self, RequestContext};
+
+pub static REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+// the id of the request being served, available to the endpoints as an extension
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+#[derive(Debug, Default, Clone)]
+pub struct RequestLogMiddleware;
+
+impl RequestLogMiddleware {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+// only accept ids that are safe to echo back and write to the logs
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+}
+
+#[tide::utils::async_trait]
+impl Middleware<State> for RequestLogMiddleware {
+    async fn handle(&self, mut req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let start = Instant::now();
+        let request_id = req
+            .header(REQUEST_ID_HEADER)
+            .map(|value| value.as_str().to_string())
+            .filter(|id| valid_request_id(id))
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let user_id: Option<String> = req.session().get("user_id");
+        let method = req.method().to_string();
+        let path = req.url().path().to_string();
+
+        req.set_ext(RequestId(request_id.clone()));
+        logger::set_context(Some(RequestContext {
+            request_id: request_id.clone(),
+            user_id,
+        }));
+
+        let mut res = next.run(req).await;
+        res.insert_header(REQUEST_ID_HEADER, request_id.as_str());
+
+        let status = res.status();
+        let route = route_label(&res);
+        let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+        if status.is_server_error() {
+            let error = res.error().map(|e| format!("{:?}", e)).unwrap_or_default();
+            tide::log::error!("request failed", {
+                method: method,
+                route: route,
+                path: path,
+                status: status as u16,
+                latency_ms: latency_ms,
+                error: error,
+            });
+        } else if status.is_client_error() {
+            tide::log::warn!("request rejected", {
+                method: method,
+                route: route,
+                path: path,
+                status: status as u16,
+                latency_ms: latency_ms,
+            });
+        } else {
+            tide::log::info!("request served", {
+                method: method,
+                route: route,
+                path: path,
+                status: status as u16,
+                latency_ms: latency_ms,
+            });
+        }
+
+        logger::set_context(None);
+        Ok(res)
+    }
+}
@@ -0,0 +1,62 @@
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::{FutureExt, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use tide::{Middleware, Next, Request};
+
+use super::route_label;
+use crate::telemetry;
+
+// Starts a server span per request, continuing the W3C `traceparent` of the caller if any.
+#[derive(Debug, Default, Clone)]
+pub struct TraceMiddleware;
+
+impl TraceMiddleware {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+struct HeaderExtractor<'a, S>(&'a Request<S>);
+
+impl<'a, S> Extractor for HeaderExtractor<'a, S> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.header(key).map(|value| value.as_str())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.header_names().map(|name| name.as_str()).collect()
+    }
+}
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> Middleware<S> for TraceMiddleware {
+    async fn handle(&self, req: Request<S>, next: Next<'_, S>) -> tide::Result {
+        let parent = TraceContextPropagator::new().extract(&HeaderExtractor(&req));
+        let method = req.method().to_string();
+
+        // named after the method until the route is known
+        let tracer = telemetry::tracer();
+        let span = tracer
+            .span_builder(method.clone())
+            .with_kind(SpanKind::Server)
+            .with_attributes(vec![KeyValue::new("http.request.method", method.clone())])
+            .start_with_context(&tracer, &parent);
+        let cx = parent.with_span(span);
+
+        let res = next.run(req).with_context(cx.clone()).await;
+
+        let span = cx.span();
+        let route = route_label(&res);
+        span.update_name(format!("{} {}", method, route));
+        span.set_attribute(KeyValue::new("http.route", route));
+        let status = res.status();
+        span.set_attribute(KeyValue::new("http.response.status_code", status as i64));
+        if status.is_server_error() {
+            span.set_status(Status::error(status.canonical_reason()));
+        }
+        span.end();
+
+        Ok(res)
+    }
+}
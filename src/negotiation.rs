@@ -0,0 +1,53 @@
+use tide::http::headers;
+use tide::{Body, Request, Response};
+
+// The first of the `available` media types preferred by the `Accept` header of the request,
+// a request without `Accept` gets the first one.
+pub fn negotiate<State>(req: &Request<State>, available: &[&'static str]) -> Option<&'static str> {
+    let accept = match req.header(headers::ACCEPT) {
+        None => return available.first().copied(),
+        Some(accept) => accept.as_str(),
+    };
+
+    let mut ranges: Vec<(&str, f32)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media_range = parts.next().filter(|r| !r.is_empty())?;
+            let quality = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            Some((media_range, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+    // the most specific ranges win between the ones of the same quality
+    ranges.sort_by(|(a, qa), (b, qb)| {
+        qb.partial_cmp(qa)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.matches('*').count().cmp(&b.matches('*').count()))
+    });
+
+    ranges.into_iter().find_map(|(media_range, _)| {
+        available
+            .iter()
+            .copied()
+            .find(|media_type| matches(media_range, media_type))
+    })
+}
+
+// The 406 response of a request for none of the `available` media types.
+pub fn not_acceptable(available: &[&str]) -> Response {
+    let mut res = Response::new(406);
+    res.set_body(Body::from(serde_json::json!({ "acceptable": available })));
+    res
+}
+
+// `*/*` and `text/*` ranges match any type and any text type.
+fn matches(media_range: &str, media_type: &str) -> bool {
+    let (range_type, range_subtype) = media_range.split_once('/').unwrap_or((media_range, ""));
+    let (type_, subtype) = media_type.split_once('/').unwrap_or((media_type, ""));
+    (range_type == "*" || range_type.eq_ignore_ascii_case(type_))
+        && (range_subtype == "*" || range_subtype.eq_ignore_ascii_case(subtype))
+}
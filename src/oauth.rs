@@ -0,0 +1,99 @@
+use super::*;
+
+use oauth2::reqwest::async_http_client;
+use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
+use oauth2::{AuthorizationCode, CsrfToken, Scope, TokenResponse};
+use std::fmt::Debug;
+use tide::http;
+
+static AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+static TOKEN_URL: &str = "https://www.googleapis.com/oauth2/v3/token";
+static USERINFO_URL: &str = "https://www.googleapis.com/oauth2/v2/userinfo";
+
+// static AUTH_GOOGLE_SCOPE_EMAIL: &str = "https://www.googleapis.com/auth/userinfo.email";
+static AUTH_GOOGLE_SCOPE_PROFILE: &str = "https://www.googleapis.com/auth/userinfo.profile";
+
+// The user a login was made for.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserInfo {
+    pub id: String,
+    #[serde(rename = "given_name")]
+    pub name: String,
+}
+
+// Where the users log in, and who they are once they are back with a code.
+#[tide::utils::async_trait]
+pub trait AuthProvider: Debug + Send + Sync {
+    // the label of the logins in the metrics
+    fn name(&self) -> &'static str;
+
+    fn authorize_url(&self) -> String;
+
+    async fn user_info(&self, code: &str) -> tide::Result<UserInfo>;
+}
+
+#[derive(Debug, Clone)]
+pub struct GoogleAuthProvider {
+    client: BasicClient,
+}
+
+impl GoogleAuthProvider {
+    pub fn from_env() -> tide::Result<Self> {
+        let client = BasicClient::new(
+            ClientId::new(
+                std::env::var("OAUTH_GOOGLE_CLIENT_ID")
+                    .expect("missing env var OAUTH_GOOGLE_CLIENT_ID"),
+            ),
+            Some(ClientSecret::new(
+                std::env::var("OAUTH_GOOGLE_CLIENT_SECRET")
+                    .expect("missing env var OAUTH_GOOGLE_CLIENT_SECRET"),
+            )),
+            AuthUrl::new(AUTH_URL.to_string())?,
+            Some(TokenUrl::new(TOKEN_URL.to_string())?),
+        )
+        .set_redirect_uri(RedirectUrl::new(
+            std::env::var("OAUTH_GOOGLE_REDIRECT_URL")
+                .expect("missing env var OAUTH_GOOGLE_REDIRECT_URL"),
+        )?);
+
+        Ok(GoogleAuthProvider { client })
+    }
+}
+
+#[tide::utils::async_trait]
+impl AuthProvider for GoogleAuthProvider {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    fn authorize_url(&self) -> String {
+        let (auth_url, _csrf_token) = self
+            .client
+            .authorize_url(CsrfToken::new_random)
+            // Set the desired scopes.
+            // .add_scope(Scope::new(AUTH_GOOGLE_SCOPE_EMAIL.to_string()))
+            .add_scope(Scope::new(AUTH_GOOGLE_SCOPE_PROFILE.to_string()))
+            .url();
+
+        auth_url.to_string()
+    }
+
+    async fn user_info(&self, code: &str) -> tide::Result<UserInfo> {
+        let token = self
+            .client
+            .exchange_code(AuthorizationCode::new(code.to_string()))
+            .request_async(async_http_client)
+            .await
+            .map_err(|_| Error::from_str(401, "error"))?;
+
+        let userinfo = surf::get(USERINFO_URL)
+            .header(
+                http::headers::AUTHORIZATION,
+                format!("Bearer {}", token.access_token().secret()),
+            )
+            .recv_json()
+            .await?;
+
+        Ok(userinfo)
+    }
+}
@@ -0,0 +1,439 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use serde_json::{json, Map, Value};
+use tide::http::Method;
+
+use crate::controllers::webhook::NewWebhook;
+use crate::handlers::audit::AuditRecord;
+use crate::handlers::revision::Revision;
+use crate::handlers::webhook::{Delivery, Webhook, WebhookSummary};
+use crate::import::ImportReport;
+use crate::middlewares::errors::ApiError;
+use crate::{BatchRequest, BatchResponse, Dino, NewDino, TrashedDino};
+
+// The OpenAPI 3 document for the given routes of the JSON api, served under `prefix`.
+pub fn document(prefix: &str, routes: &[(Method, &str)]) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+
+    let mut paths = Map::new();
+    for (method, path) in routes {
+        let item = paths.entry(openapi_path(path)).or_insert_with(|| json!({}));
+        if let Some(operation) = operation(*method, path, &mut gen) {
+            item[method.to_string().to_lowercase()] = operation;
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Tide basic CRUD",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": prefix }],
+        "paths": paths,
+        "components": {
+            "schemas": gen.take_definitions(),
+        },
+    })
+}
+
+// `/dinos/:id` -> `/dinos/{id}`
+fn openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(param) => format!("{{{}}}", param),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// The spec entry of a route, every route of the api needs one.
+pub fn operation(method: Method, path: &str, gen: &mut SchemaGenerator) -> Option<Value> {
+    let dino = json!(gen.subschema_for::<Dino>());
+    let id_param = json!({
+        "name": "id",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string", "format": "uuid" },
+    });
+
+    let mut operation = match (method, path) {
+        (Method::Get, "/dinos") => json!({
+            "operationId": "listDinos",
+            "summary": "List all the dinos",
+            "description": "The representation is chosen by the `Accept` header, JSON by default.",
+            "responses": {
+                "200": {
+                    "description": "The dinos",
+                    "content": {
+                        "application/json": { "schema": { "type": "array", "items": dino } },
+                        "text/html": { "schema": { "type": "string" } },
+                        "text/csv": { "schema": { "type": "string" } },
+                    },
+                },
+                "406": {
+                    "description": "None of the accepted types is available",
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "acceptable": { "type": "array", "items": { "type": "string" } },
+                                },
+                            },
+                        },
+                    },
+                },
+            },
+        }),
+        (Method::Post, "/dinos") => json!({
+            "operationId": "createDino",
+            "summary": "Create a dino, owned by the logged in user if any",
+            "description": "The id is generated by the server when the body doesn't have one.",
+            "parameters": [{
+                "name": "Idempotency-Key",
+                "in": "header",
+                "required": false,
+                "description": "Retries of the logged in user with the same key get the first response back instead of creating another dino",
+                "schema": { "type": "string", "maxLength": 255 },
+            }],
+            "requestBody": json_body(json!(gen.subschema_for::<NewDino>())),
+            "responses": {
+                "201": {
+                    "description": "The created dino",
+                    "headers": {
+                        "Location": {
+                            "description": "The url of the created dino",
+                            "schema": { "type": "string" },
+                        },
+                    },
+                    "content": { "application/json": { "schema": dino } },
+                },
+                "401": { "description": "An Idempotency-Key was sent without a logged in user" },
+                "409": { "description": "A dino with the same id already exists, or a request with the same Idempotency-Key is in progress" },
+                "422": { "description": "The body is not a valid dino, or the Idempotency-Key was already used with a different request" },
+            },
+        }),
+        (Method::Get, "/dinos/export") => json!({
+            "operationId": "exportDinos",
+            "summary": "Download all the dinos",
+            "description": "The rows are streamed from the database as they are sent.",
+            "parameters": [{
+                "name": "format",
+                "in": "query",
+                "required": false,
+                "schema": { "type": "string", "enum": ["csv", "ndjson", "json"], "default": "json" },
+            }],
+            "responses": {
+                "200": {
+                    "description": "The dinos, as an attachment",
+                    "headers": {
+                        "Content-Disposition": { "schema": { "type": "string" } },
+                    },
+                    "content": {
+                        "application/json": { "schema": { "type": "array", "items": dino } },
+                        "application/x-ndjson": { "schema": { "type": "string" } },
+                        "text/csv": { "schema": { "type": "string" } },
+                    },
+                },
+            },
+        }),
+        (Method::Get, "/dinos/events") => json!({
+            "operationId": "dinoEvents",
+            "summary": "Stream the changes of the dinos",
+            "description": "Server-sent events named `created`, `updated` or `deleted`, with the dino as data.",
+            "responses": {
+                "200": {
+                    "description": "The event stream",
+                    "content": { "text/event-stream": { "schema": { "type": "string" } } },
+                },
+            },
+        }),
+        (Method::Get, "/dinos/ws") => json!({
+            "operationId": "dinoSocket",
+            "summary": "Subscribe to the dino changes and change dinos over a websocket",
+            "description": "JSON text messages with a `type`. The client sends `subscribe` (`id`, `filter` by `id`, `diet` or `owner`), `unsubscribe` (`id`), `command` (`ref` and a batch operation), `get` (`ref`, `id`) and `ping`. The server answers with `subscribed`, `unsubscribed`, `result` (`ref`, `status`, `dino` or `error`), `pong` and `error`, and pushes an `event` (`subscription`, `kind`, `dino`) for every matching change. A client holds at most 32 subscriptions. The server pings every 30 seconds, closes idle clients after 75 seconds and clients too slow to read their events with code 1013.",
+            "responses": {
+                "101": { "description": "Switched to the websocket protocol" },
+                "403": { "description": "The Origin of the request is another site" },
+                "426": { "description": "Not a websocket request" },
+            },
+        }),
+        (Method::Get, "/dinos/trash") => json!({
+            "operationId": "listTrashedDinos",
+            "summary": "List the deleted dinos the logged in user can restore",
+            "description": "Deleted dinos are purged once they have been in the trash for longer than the retention.",
+            "responses": {
+                "200": json_response(
+                    "The deleted dinos, the most recent first",
+                    json!({ "type": "array", "items": gen.subschema_for::<TrashedDino>() }),
+                ),
+            },
+        }),
+        (Method::Post, "/dinos/batch") => json!({
+            "operationId": "batchDinos",
+            "summary": "Create, update and delete dinos in one request",
+            "description": "Operations run in one transaction unless `atomic` is false, in which case each one is applied on its own.",
+            "requestBody": json_body(json!(gen.subschema_for::<BatchRequest>())),
+            "responses": {
+                "200": json_response(
+                    "The result of each operation",
+                    json!(gen.subschema_for::<BatchResponse>()),
+                ),
+                "409": json_response(
+                    "An operation failed and the batch was rolled back",
+                    json!(gen.subschema_for::<BatchResponse>()),
+                ),
+                "413": { "description": "Too many operations in the batch" },
+            },
+        }),
+        (Method::Post, "/dinos/import") => json!({
+            "operationId": "importDinos",
+            "summary": "Import dinos from a CSV or NDJSON file",
+            "description": "Rows are imported in one transaction, which is only committed when every row is valid.",
+            "parameters": [
+                {
+                    "name": "format",
+                    "in": "query",
+                    "required": false,
+                    "description": "Overrides the format given by the Content-Type",
+                    "schema": { "type": "string", "enum": ["csv", "ndjson"] },
+                },
+                {
+                    "name": "dry_run",
+                    "in": "query",
+                    "required": false,
+                    "description": "Validate the rows without saving them",
+                    "schema": { "type": "boolean", "default": false },
+                },
+                {
+                    "name": "upsert",
+                    "in": "query",
+                    "required": false,
+                    "description": "Update the dinos whose id already exists",
+                    "schema": { "type": "boolean", "default": false },
+                },
+                {
+                    "name": "mapping",
+                    "in": "query",
+                    "required": false,
+                    "description": "The csv column of each dino field, e.g. `mapping[weight]=Mass`",
+                    "style": "deepObject",
+                    "schema": { "type": "object", "additionalProperties": { "type": "string" } },
+                },
+            ],
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "text/csv": { "schema": { "type": "string" } },
+                    "application/x-ndjson": { "schema": { "type": "string" } },
+                },
+            },
+            "responses": {
+                "200": json_response(
+                    "Every row was imported",
+                    json!(gen.subschema_for::<ImportReport>()),
+                ),
+                "415": { "description": "Unsupported import format" },
+                "422": json_response(
+                    "Some rows are not valid, nothing was imported",
+                    json!(gen.subschema_for::<ImportReport>()),
+                ),
+            },
+        }),
+        (Method::Get, "/dinos/:id") => json!({
+            "operationId": "getDino",
+            "summary": "Get a dino",
+            "parameters": [id_param],
+            "responses": {
+                "200": json_response("The dino", dino),
+                "400": { "description": "Malformed id" },
+                "404": { "description": "Dino not found" },
+            },
+        }),
+        (Method::Put, "/dinos/:id") => json!({
+            "operationId": "updateDino",
+            "summary": "Replace a dino",
+            "parameters": [id_param],
+            "requestBody": json_body(dino.clone()),
+            "responses": {
+                "200": json_response("The updated dino", dino),
+                "400": { "description": "Malformed id" },
+                "401": { "description": "The dino belongs to another user" },
+                "404": { "description": "Dino not found" },
+                "422": { "description": "The body is not a valid dino" },
+            },
+        }),
+        (Method::Patch, "/dinos/:id") => json!({
+            "operationId": "patchDino",
+            "summary": "Update some fields of a dino",
+            "parameters": [id_param],
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/merge-patch+json": {
+                        "schema": { "type": "object", "description": "RFC 7396 JSON Merge Patch" },
+                    },
+                    "application/json-patch+json": {
+                        "schema": {
+                            "type": "array",
+                            "description": "RFC 6902 JSON Patch",
+                            "items": { "type": "object" },
+                        },
+                    },
+                },
+            },
+            "responses": {
+                "200": json_response("The updated dino", dino),
+                "400": { "description": "Malformed id or patch document" },
+                "401": { "description": "The dino belongs to another user" },
+                "404": { "description": "Dino not found" },
+                "409": { "description": "The patch can't be applied to the dino" },
+                "415": { "description": "Unsupported patch format" },
+                "422": { "description": "The patched dino is not valid" },
+            },
+        }),
+        (Method::Delete, "/dinos/:id") => json!({
+            "operationId": "deleteDino",
+            "summary": "Move a dino to the trash",
+            "parameters": [id_param],
+            "responses": {
+                "204": { "description": "Dino deleted" },
+                "400": { "description": "Malformed id" },
+                "401": { "description": "The dino belongs to another user" },
+                "404": { "description": "Dino not found" },
+            },
+        }),
+        (Method::Get, "/dinos/:id/history") => json!({
+            "operationId": "dinoHistory",
+            "summary": "List the changes of a dino, the oldest first",
+            "parameters": [id_param],
+            "responses": {
+                "200": json_response(
+                    "Who changed the dino, when and what changed",
+                    json!({ "type": "array", "items": gen.subschema_for::<AuditRecord>() }),
+                ),
+                "400": { "description": "Malformed id" },
+                "404": { "description": "Dino not found" },
+            },
+        }),
+        (Method::Get, "/dinos/:id/revisions") => json!({
+            "operationId": "dinoRevisions",
+            "summary": "List the revisions of a dino, the oldest first",
+            "parameters": [id_param],
+            "responses": {
+                "200": json_response(
+                    "A snapshot of the dino after each change",
+                    json!({ "type": "array", "items": gen.subschema_for::<Revision>() }),
+                ),
+                "400": { "description": "Malformed id" },
+                "404": { "description": "Dino not found" },
+            },
+        }),
+        (Method::Post, "/dinos/:id/revisions/:rev/revert") => json!({
+            "operationId": "revertDino",
+            "summary": "Restore an earlier revision of a dino",
+            "description": "The restored snapshot is saved as a new revision.",
+            "parameters": [
+                id_param,
+                {
+                    "name": "rev",
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "integer" },
+                },
+            ],
+            "responses": {
+                "200": json_response("The reverted dino", dino),
+                "400": { "description": "Malformed id or revision number" },
+                "401": { "description": "The dino belongs to another user" },
+                "404": { "description": "Dino or revision not found" },
+            },
+        }),
+        (Method::Post, "/dinos/:id/restore") => json!({
+            "operationId": "restoreDino",
+            "summary": "Restore a dino from the trash",
+            "parameters": [id_param],
+            "responses": {
+                "200": json_response("The restored dino", dino),
+                "400": { "description": "Malformed id" },
+                "401": { "description": "The dino belongs to another user" },
+                "404": { "description": "Dino not found in the trash" },
+            },
+        }),
+        (Method::Get, "/webhooks") => json!({
+            "operationId": "listWebhooks",
+            "summary": "List the webhooks of the logged in user",
+            "responses": {
+                "200": json_response(
+                    "The webhooks, without their secrets",
+                    json!({ "type": "array", "items": gen.subschema_for::<WebhookSummary>() }),
+                ),
+                "401": { "description": "Not logged in" },
+            },
+        }),
+        (Method::Post, "/webhooks") => json!({
+            "operationId": "createWebhook",
+            "summary": "Register a url notified of the dino changes",
+            "description": "Every matching change is POSTed to the url as `{id, event, created_at, dino}`, with the event in `X-Webhook-Event`, the delivery id in `X-Webhook-Delivery` and `sha256=<hex HMAC-SHA256 of the body keyed with the secret>` in `X-Webhook-Signature`. Failed deliveries are retried with an exponential backoff until they're dead.",
+            "requestBody": json_body(json!(gen.subschema_for::<NewWebhook>())),
+            "responses": {
+                "201": json_response("The webhook, with its secret, shown this once", json!(gen.subschema_for::<Webhook>())),
+                "401": { "description": "Not logged in" },
+                "422": { "description": "Invalid url, a url resolving to a private, loopback or link-local address, or no events" },
+            },
+        }),
+        (Method::Delete, "/webhooks/:id") => json!({
+            "operationId": "deleteWebhook",
+            "summary": "Delete a webhook and its pending deliveries",
+            "parameters": [id_param],
+            "responses": {
+                "204": { "description": "Webhook deleted" },
+                "400": { "description": "Malformed id" },
+                "401": { "description": "Not logged in" },
+                "404": { "description": "Webhook not found" },
+            },
+        }),
+        (Method::Get, "/webhooks/:id/deliveries") => json!({
+            "operationId": "webhookDeliveries",
+            "summary": "List the deliveries of a webhook, the newest first",
+            "parameters": [id_param],
+            "responses": {
+                "200": json_response(
+                    "Each delivery with its status, `pending`, `delivered` or `dead`, and its last attempt",
+                    json!({ "type": "array", "items": gen.subschema_for::<Delivery>() }),
+                ),
+                "400": { "description": "Malformed id" },
+                "401": { "description": "Not logged in" },
+                "404": { "description": "Webhook not found" },
+            },
+        }),
+        _ => return None,
+    };
+
+    // the errors without a body of their own get the one of the api errors
+    let error = json!(gen.subschema_for::<ApiError>());
+    if let Some(responses) = operation["responses"].as_object_mut() {
+        for (status, response) in responses.iter_mut() {
+            if status.as_str() >= "400" && response.get("content").is_none() {
+                response["content"] = json!({ "application/json": { "schema": error } });
+            }
+        }
+    }
+
+    Some(operation)
+}
+
+fn json_body(schema: Value) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
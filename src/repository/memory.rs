@@ -0,0 +1,168 @@
+use super::*;
+
+use crate::handlers::dino::{validate, validate_changes};
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+
+// A dino and when it was moved to the trash.
+type Entry = (Dino, Option<DateTime<Utc>>);
+
+// The dinos in a vector, for the tests that don't need a database. Nothing is audited,
+// revisioned or notified.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDinoRepository {
+    dinos: Arc<Mutex<Vec<Entry>>>,
+}
+
+impl MemoryDinoRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Vec<Entry>) -> T) -> T {
+        let mut dinos = self.dinos.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut dinos)
+    }
+
+    fn live(&self, id: Uuid) -> Option<Dino> {
+        self.with(|dinos| {
+            dinos
+                .iter()
+                .find(|(dino, deleted_at)| dino.id == id && deleted_at.is_none())
+                .map(|(dino, _)| dino.clone())
+        })
+    }
+}
+
+fn trashed(dino: &Dino, deleted_at: DateTime<Utc>) -> TrashedDino {
+    TrashedDino {
+        id: dino.id,
+        name: dino.name.clone(),
+        weight: dino.weight,
+        diet: dino.diet.clone(),
+        user_id: dino.user_id.clone(),
+        deleted_at,
+    }
+}
+
+#[tide::utils::async_trait]
+impl DinoRepository for MemoryDinoRepository {
+    async fn list(&self) -> tide::Result<Vec<Dino>> {
+        Ok(self.with(|dinos| {
+            dinos
+                .iter()
+                .filter(|(_, deleted_at)| deleted_at.is_none())
+                .map(|(dino, _)| dino.clone())
+                .collect()
+        }))
+    }
+
+    fn stream(&self) -> DinoStream<'_> {
+        let dinos = self.with(|dinos| {
+            dinos
+                .iter()
+                .filter(|(_, deleted_at)| deleted_at.is_none())
+                .map(|(dino, _)| Ok(dino.clone()))
+                .collect::<Vec<_>>()
+        });
+        Box::pin(async_std::stream::from_iter(dinos))
+    }
+
+    async fn get(&self, id: Uuid) -> tide::Result<Option<Dino>> {
+        Ok(self.live(id))
+    }
+
+    async fn create(&self, dino: Dino, _actor: &Actor) -> tide::Result<Dino> {
+        validate(&dino)?;
+        self.with(|dinos| {
+            // like the primary key of the table, trashed dinos included
+            if dinos.iter().any(|(existing, _)| existing.id == dino.id) {
+                return Err(Error::from_str(
+                    409,
+                    "duplicate key value violates unique constraint \"dinos_pkey\"",
+                ));
+            }
+            dinos.push((dino.clone(), None));
+            Ok(dino)
+        })
+    }
+
+    async fn update(&self, id: Uuid, dino: Dino, _actor: &Actor) -> tide::Result<Option<Dino>> {
+        validate(&dino)?;
+        Ok(self.with(|dinos| {
+            let (current, _) = dinos
+                .iter_mut()
+                .find(|(current, deleted_at)| current.id == id && deleted_at.is_none())?;
+            *current = Dino { id, ..dino };
+            Some(current.clone())
+        }))
+    }
+
+    async fn patch(
+        &self,
+        id: Uuid,
+        changes: DinoChanges,
+        _actor: &Actor,
+    ) -> tide::Result<Option<Dino>> {
+        validate_changes(&changes)?;
+        Ok(self.with(|dinos| {
+            let (current, _) = dinos
+                .iter_mut()
+                .find(|(current, deleted_at)| current.id == id && deleted_at.is_none())?;
+            if let Some(name) = changes.name {
+                current.name = name;
+            }
+            if let Some(weight) = changes.weight {
+                current.weight = weight;
+            }
+            if let Some(diet) = changes.diet {
+                current.diet = diet;
+            }
+            if let Some(user_id) = changes.user_id {
+                current.user_id = user_id;
+            }
+            Some(current.clone())
+        }))
+    }
+
+    async fn delete(&self, id: Uuid, _actor: &Actor) -> tide::Result<Option<()>> {
+        Ok(self.with(|dinos| {
+            let (_, deleted_at) = dinos
+                .iter_mut()
+                .find(|(dino, deleted_at)| dino.id == id && deleted_at.is_none())?;
+            *deleted_at = Some(Utc::now());
+            Some(())
+        }))
+    }
+
+    async fn trash(&self, user_id: &str) -> tide::Result<Vec<TrashedDino>> {
+        let mut rows: Vec<TrashedDino> = self.with(|dinos| {
+            dinos
+                .iter()
+                .filter(|(dino, _)| dino.user_id.as_deref().is_none_or(|owner| owner == user_id))
+                .filter_map(|(dino, deleted_at)| deleted_at.map(|at| trashed(dino, at)))
+                .collect()
+        });
+        rows.sort_by_key(|row| std::cmp::Reverse(row.deleted_at));
+        Ok(rows)
+    }
+
+    async fn get_trashed(&self, id: Uuid) -> tide::Result<Option<TrashedDino>> {
+        Ok(self.with(|dinos| {
+            dinos
+                .iter()
+                .filter(|(dino, _)| dino.id == id)
+                .find_map(|(dino, deleted_at)| deleted_at.map(|at| trashed(dino, at)))
+        }))
+    }
+
+    async fn restore(&self, id: Uuid, _actor: &Actor) -> tide::Result<Option<Dino>> {
+        Ok(self.with(|dinos| {
+            let (dino, deleted_at) = dinos
+                .iter_mut()
+                .find(|(dino, deleted_at)| dino.id == id && deleted_at.is_some())?;
+            *deleted_at = None;
+            Some(dino.clone())
+        }))
+    }
+}
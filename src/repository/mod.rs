@@ -0,0 +1,52 @@
+use super::*;
+
+use crate::handlers::audit::Actor;
+use crate::{DinoChanges, TrashedDino};
+use async_std::stream::Stream;
+use std::pin::Pin;
+
+pub mod memory;
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use memory::MemoryDinoRepository;
+pub use postgres::PgDinoRepository;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteDinoRepository;
+
+pub type DinoStream<'a> = Pin<Box<dyn Stream<Item = tide::Result<Dino>> + Send + 'a>>;
+
+// Where the dinos are kept. The batches, imports and websocket commands still run in a Postgres
+// transaction of their own, they don't go through it.
+#[tide::utils::async_trait]
+pub trait DinoRepository: std::fmt::Debug + Send + Sync {
+    // the dinos that aren't in the trash
+    async fn list(&self) -> tide::Result<Vec<Dino>>;
+
+    // like `list`, one dino at a time as the stream is polled
+    fn stream(&self) -> DinoStream<'_>;
+
+    async fn get(&self, id: Uuid) -> tide::Result<Option<Dino>>;
+
+    async fn create(&self, dino: Dino, actor: &Actor) -> tide::Result<Dino>;
+
+    async fn update(&self, id: Uuid, dino: Dino, actor: &Actor) -> tide::Result<Option<Dino>>;
+
+    async fn patch(
+        &self,
+        id: Uuid,
+        changes: DinoChanges,
+        actor: &Actor,
+    ) -> tide::Result<Option<Dino>>;
+
+    // moves a dino to the trash
+    async fn delete(&self, id: Uuid, actor: &Actor) -> tide::Result<Option<()>>;
+
+    // the dinos in the trash that `user_id` can restore, the most recently deleted first
+    async fn trash(&self, user_id: &str) -> tide::Result<Vec<TrashedDino>>;
+
+    async fn get_trashed(&self, id: Uuid) -> tide::Result<Option<TrashedDino>>;
+
+    async fn restore(&self, id: Uuid, actor: &Actor) -> tide::Result<Option<Dino>>;
+}
@@ -0,0 +1,61 @@
+use super::*;
+
+// The dinos table, every change is audited, revisioned and notified in its transaction.
+#[derive(Debug, Clone)]
+pub struct PgDinoRepository {
+    db_pool: PgPool,
+}
+
+impl PgDinoRepository {
+    pub fn new(db_pool: PgPool) -> Self {
+        PgDinoRepository { db_pool }
+    }
+}
+
+#[tide::utils::async_trait]
+impl DinoRepository for PgDinoRepository {
+    async fn list(&self) -> tide::Result<Vec<Dino>> {
+        handlers::dino::list(&self.db_pool).await
+    }
+
+    fn stream(&self) -> DinoStream<'_> {
+        Box::pin(handlers::dino::stream(&self.db_pool))
+    }
+
+    async fn get(&self, id: Uuid) -> tide::Result<Option<Dino>> {
+        handlers::dino::get(id, &self.db_pool).await
+    }
+
+    async fn create(&self, dino: Dino, actor: &Actor) -> tide::Result<Dino> {
+        handlers::dino::create(dino, actor, &self.db_pool).await
+    }
+
+    async fn update(&self, id: Uuid, dino: Dino, actor: &Actor) -> tide::Result<Option<Dino>> {
+        handlers::dino::update(id, dino, actor, &self.db_pool).await
+    }
+
+    async fn patch(
+        &self,
+        id: Uuid,
+        changes: DinoChanges,
+        actor: &Actor,
+    ) -> tide::Result<Option<Dino>> {
+        handlers::dino::patch(id, changes, actor, &self.db_pool).await
+    }
+
+    async fn delete(&self, id: Uuid, actor: &Actor) -> tide::Result<Option<()>> {
+        handlers::dino::delete(id, actor, &self.db_pool).await
+    }
+
+    async fn trash(&self, user_id: &str) -> tide::Result<Vec<TrashedDino>> {
+        handlers::dino::trash(user_id, &self.db_pool).await
+    }
+
+    async fn get_trashed(&self, id: Uuid) -> tide::Result<Option<TrashedDino>> {
+        handlers::dino::get_trashed(id, &self.db_pool).await
+    }
+
+    async fn restore(&self, id: Uuid, actor: &Actor) -> tide::Result<Option<Dino>> {
+        handlers::dino::restore(id, actor, &self.db_pool).await
+    }
+}
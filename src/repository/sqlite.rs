@@ -0,0 +1,299 @@
+use super::*;
+
+use crate::handlers::dino::{validate, validate_changes};
+use crate::metrics::DB_QUERY_DURATION;
+use crate::telemetry::{query_span, SQLITE};
+use async_std::stream::StreamExt;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::{query, query_as};
+use std::str::FromStr;
+
+// Opens the SQLite file of `db_url` (e.g. `sqlite:dinos.db`), created and migrated if needed.
+pub async fn make_db_pool(db_url: &str) -> tide::Result<SqlitePool> {
+    let options = SqliteConnectOptions::from_str(db_url)?.create_if_missing(true);
+    let db_pool = SqlitePoolOptions::new().connect_with(options).await?;
+    sqlx::migrate!("./migrations/sqlite")
+        .run(&db_pool)
+        .await
+        .map_err(|e| Error::new(500, e))?;
+
+    Ok(db_pool)
+}
+
+// The queries of `handlers::dino` for SQLite. The ids are stored as text, and nothing is
+// audited, revisioned or notified.
+#[derive(Debug, Clone)]
+pub struct SqliteDinoRepository {
+    db_pool: SqlitePool,
+}
+
+impl SqliteDinoRepository {
+    pub fn new(db_pool: SqlitePool) -> Self {
+        SqliteDinoRepository { db_pool }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct DinoRow {
+    id: String,
+    name: String,
+    weight: i32,
+    diet: String,
+    user_id: Option<String>,
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+impl DinoRow {
+    fn dino(self) -> tide::Result<Dino> {
+        Ok(Dino {
+            id: Uuid::parse_str(&self.id).map_err(|e| Error::new(500, e))?,
+            name: self.name,
+            weight: self.weight,
+            diet: self.diet,
+            user_id: self.user_id,
+        })
+    }
+
+    fn trashed(self) -> tide::Result<TrashedDino> {
+        let deleted_at = self
+            .deleted_at
+            .ok_or_else(|| Error::from_str(500, "the dino isn't in the trash"))?;
+        let dino = self.dino()?;
+        Ok(TrashedDino {
+            id: dino.id,
+            name: dino.name,
+            weight: dino.weight,
+            diet: dino.diet,
+            user_id: dino.user_id,
+            deleted_at,
+        })
+    }
+}
+
+#[tide::utils::async_trait]
+impl DinoRepository for SqliteDinoRepository {
+    async fn list(&self) -> tide::Result<Vec<Dino>> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_list"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_list", "SELECT FROM dinos");
+        let rows: Vec<DinoRow> = query_as(
+            r#"
+            SELECT id, name, weight, diet, user_id, deleted_at from dinos
+            WHERE deleted_at IS NULL
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        rows.into_iter().map(DinoRow::dino).collect()
+    }
+
+    fn stream(&self) -> DinoStream<'_> {
+        let timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_stream"])
+            .start_timer();
+        let span = query_span(SQLITE, "dino_stream", "SELECT FROM dinos");
+        let rows = query_as::<_, DinoRow>(
+            r#"
+            SELECT id, name, weight, diet, user_id, deleted_at from dinos
+            WHERE deleted_at IS NULL
+            "#,
+        )
+        .fetch(&self.db_pool)
+        .map(move |row| {
+            // the timer and the span end when the stream is dropped
+            let _ = (&timer, &span);
+            row.map_err(|e| Error::new(409, e)).and_then(DinoRow::dino)
+        });
+        Box::pin(rows)
+    }
+
+    async fn get(&self, id: Uuid) -> tide::Result<Option<Dino>> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_get"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_get", "SELECT FROM dinos");
+        let row: Option<DinoRow> = query_as(
+            r#"
+            SELECT id, name, weight, diet, user_id, deleted_at from dinos
+            WHERE id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        row.map(DinoRow::dino).transpose()
+    }
+
+    async fn create(&self, dino: Dino, _actor: &Actor) -> tide::Result<Dino> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_create"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_create", "INSERT INTO dinos");
+        validate(&dino)?;
+        let row: DinoRow = query_as(
+            r#"
+            INSERT INTO dinos (id, name, weight, diet, user_id) VALUES
+            (?, ?, ?, ?, ?) returning id, name, weight, diet, user_id, deleted_at
+            "#,
+        )
+        .bind(dino.id.to_string())
+        .bind(dino.name)
+        .bind(dino.weight)
+        .bind(dino.diet)
+        .bind(dino.user_id)
+        .fetch_one(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        row.dino()
+    }
+
+    async fn update(&self, id: Uuid, dino: Dino, _actor: &Actor) -> tide::Result<Option<Dino>> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_update"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_update", "UPDATE dinos");
+        validate(&dino)?;
+        let row: Option<DinoRow> = query_as(
+            r#"
+            UPDATE dinos SET name = ?2, weight = ?3, diet = ?4, user_id = ?5
+            WHERE id = ?1 AND deleted_at IS NULL
+            returning id, name, weight, diet, user_id, deleted_at
+            "#,
+        )
+        .bind(id.to_string())
+        .bind(dino.name)
+        .bind(dino.weight)
+        .bind(dino.diet)
+        .bind(dino.user_id)
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        row.map(DinoRow::dino).transpose()
+    }
+
+    async fn patch(
+        &self,
+        id: Uuid,
+        changes: DinoChanges,
+        _actor: &Actor,
+    ) -> tide::Result<Option<Dino>> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_patch"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_patch", "UPDATE dinos");
+        validate_changes(&changes)?;
+        let (set_user_id, user_id) = match changes.user_id {
+            Some(user_id) => (true, user_id),
+            None => (false, None),
+        };
+        let row: Option<DinoRow> = query_as(
+            r#"
+            UPDATE dinos SET
+                name = COALESCE(?2, name),
+                weight = COALESCE(?3, weight),
+                diet = COALESCE(?4, diet),
+                user_id = CASE WHEN ?5 THEN ?6 ELSE user_id END
+            WHERE id = ?1 AND deleted_at IS NULL
+            returning id, name, weight, diet, user_id, deleted_at
+            "#,
+        )
+        .bind(id.to_string())
+        .bind(changes.name)
+        .bind(changes.weight)
+        .bind(changes.diet)
+        .bind(set_user_id)
+        .bind(user_id)
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        row.map(DinoRow::dino).transpose()
+    }
+
+    async fn delete(&self, id: Uuid, _actor: &Actor) -> tide::Result<Option<()>> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_delete"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_delete", "UPDATE dinos");
+        let result = query(
+            r#"
+            UPDATE dinos SET deleted_at = ?2
+            WHERE id = ?1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id.to_string())
+        .bind(Utc::now())
+        .execute(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        Ok(Some(()).filter(|_| result.rows_affected() > 0))
+    }
+
+    async fn trash(&self, user_id: &str) -> tide::Result<Vec<TrashedDino>> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_trash"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_trash", "SELECT FROM dinos");
+        let rows: Vec<DinoRow> = query_as(
+            r#"
+            SELECT id, name, weight, diet, user_id, deleted_at from dinos
+            WHERE deleted_at IS NOT NULL AND (user_id = ? OR user_id IS NULL)
+            ORDER BY deleted_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        rows.into_iter().map(DinoRow::trashed).collect()
+    }
+
+    async fn get_trashed(&self, id: Uuid) -> tide::Result<Option<TrashedDino>> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_get_trashed"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_get_trashed", "SELECT FROM dinos");
+        let row: Option<DinoRow> = query_as(
+            r#"
+            SELECT id, name, weight, diet, user_id, deleted_at from dinos
+            WHERE id = ? AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        row.map(DinoRow::trashed).transpose()
+    }
+
+    async fn restore(&self, id: Uuid, _actor: &Actor) -> tide::Result<Option<Dino>> {
+        let _timer = DB_QUERY_DURATION
+            .with_label_values(&["dino_restore"])
+            .start_timer();
+        let _span = query_span(SQLITE, "dino_restore", "UPDATE dinos");
+        let row: Option<DinoRow> = query_as(
+            r#"
+            UPDATE dinos SET deleted_at = NULL
+            WHERE id = ? AND deleted_at IS NOT NULL
+            returning id, name, weight, diet, user_id, deleted_at
+            "#,
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        row.map(DinoRow::dino).transpose()
+    }
+}
@@ -0,0 +1,66 @@
+use super::*;
+
+use async_std::io::BufReader;
+
+use crate::handlers::audit::Actor;
+use crate::import::{DinoRows, ImportFormat};
+
+// real species with their estimated weight in kg, each with a fixed id so seeding is idempotent
+static SPECIES: &str = include_str!("../seeds/dinosaurs.csv");
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SeedReport {
+    pub created: usize,
+    // already there, or in the trash
+    pub skipped: usize,
+}
+
+// The dinos of the bundled data file.
+pub async fn species() -> tide::Result<Vec<Dino>> {
+    let mut rows = DinoRows::new(
+        BufReader::new(SPECIES.as_bytes()),
+        ImportFormat::Csv,
+        &Default::default(),
+    )
+    .await?;
+
+    let mut species = vec![];
+    while let Some((row, dino)) = rows.next().await? {
+        let dino = dino.map_err(|e| Error::from_str(500, format!("seed row {}: {}", row, e)))?;
+        let id = dino
+            .id
+            .ok_or_else(|| Error::from_str(500, format!("seed row {}: missing id", row)))?;
+        species.push(Dino {
+            id,
+            name: dino.name,
+            weight: dino.weight,
+            diet: dino.diet,
+            user_id: None,
+        });
+    }
+
+    Ok(species)
+}
+
+// Creates the species that aren't in `dinos` yet. A seeded dino that was deleted since stays in
+// the trash.
+pub async fn run(actor: &Actor, db_pool: &PgPool) -> tide::Result<SeedReport> {
+    let mut report = SeedReport::default();
+
+    let mut tx = db_pool.begin().await?;
+    for dino in species().await? {
+        if handlers::dino::get(dino.id, &mut tx).await?.is_some()
+            || handlers::dino::get_trashed(dino.id, &mut tx)
+                .await?
+                .is_some()
+        {
+            report.skipped += 1;
+            continue;
+        }
+        handlers::dino::create(dino, actor, &mut tx).await?;
+        report.created += 1;
+    }
+    tx.commit().await?;
+
+    Ok(report)
+}
@@ -0,0 +1,59 @@
+use super::*;
+
+use async_session::{Result, Session, SessionStore};
+
+// Keeps the sessions in Postgres, so they're shared by every instance, survive restarts and
+// can be purged from the command line.
+#[derive(Debug, Clone)]
+pub struct PgSessionStore {
+    db_pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(db_pool: PgPool) -> Self {
+        PgSessionStore { db_pool }
+    }
+}
+
+#[async_session::async_trait]
+impl SessionStore for PgSessionStore {
+    async fn load_session(&self, cookie_value: String) -> Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let session = handlers::session::load(&id, &self.db_pool)
+            .await
+            .map_err(Error::into_inner)?;
+
+        Ok(session
+            .map(serde_json::from_value::<Session>)
+            .transpose()?
+            .and_then(Session::validate))
+    }
+
+    async fn store_session(&self, session: Session) -> Result<Option<String>> {
+        handlers::session::store(
+            session.id(),
+            session.get("user_id"),
+            serde_json::to_value(&session)?,
+            session.expiry().cloned(),
+            &self.db_pool,
+        )
+        .await
+        .map_err(Error::into_inner)?;
+
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> Result {
+        handlers::session::destroy(session.id(), &self.db_pool)
+            .await
+            .map_err(Error::into_inner)
+    }
+
+    async fn clear_store(&self) -> Result {
+        handlers::session::purge(true, &self.db_pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::into_inner)
+    }
+}
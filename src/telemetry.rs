@@ -0,0 +1,86 @@
+use opentelemetry::global::{self, BoxedSpan, BoxedTracer};
+use opentelemetry::trace::{FutureExt, SpanKind, Status, TraceContextExt, TraceError, Tracer};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{Protocol, WithExportConfig};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tide::{Endpoint, Request};
+
+static TRACER_NAME: &str = "tide-basic-crud";
+
+// Export spans over OTLP/HTTP to `endpoint`, e.g. `http://localhost:4318/v1/traces`.
+pub fn init(endpoint: &str) -> Result<TracerProvider, TraceError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpJson)
+        .with_endpoint(endpoint)
+        .build()?;
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::AsyncStd)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            TRACER_NAME,
+        )]))
+        .build();
+
+    global::set_tracer_provider(provider.clone());
+    Ok(provider)
+}
+
+pub fn tracer() -> BoxedTracer {
+    global::tracer(TRACER_NAME)
+}
+
+// The `db.system` of the query spans.
+pub static POSTGRESQL: &str = "postgresql";
+pub static SQLITE: &str = "sqlite";
+
+// A span for a sql query, child of the current context. It ends when dropped.
+pub fn query_span(
+    db_system: &'static str,
+    name: &'static str,
+    statement: &'static str,
+) -> BoxedSpan {
+    let tracer = tracer();
+    tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Client)
+        .with_attributes(vec![
+            KeyValue::new("db.system", db_system),
+            KeyValue::new("db.statement", statement),
+        ])
+        .start_with_context(&tracer, &Context::current())
+}
+
+// Wraps a controller so its execution is recorded as a span.
+pub fn traced<E>(name: &'static str, endpoint: E) -> Traced<E> {
+    Traced { name, endpoint }
+}
+
+#[derive(Debug)]
+pub struct Traced<E> {
+    name: &'static str,
+    endpoint: E,
+}
+
+#[tide::utils::async_trait]
+impl<State, E> Endpoint<State> for Traced<E>
+where
+    State: Clone + Send + Sync + 'static,
+    E: Endpoint<State>,
+{
+    async fn call(&self, req: Request<State>) -> tide::Result {
+        let span = tracer().start_with_context(self.name, &Context::current());
+        let cx = Context::current_with_span(span);
+
+        let res = self.endpoint.call(req).with_context(cx.clone()).await;
+
+        let span = cx.span();
+        if let Err(e) = &res {
+            span.set_status(Status::error(e.to_string()));
+        }
+        span.end();
+        res
+    }
+}
@@ -0,0 +1,177 @@
+use super::*;
+
+use crate::handlers::webhook::{self, PendingDelivery};
+use async_std::net::ToSocketAddrs;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tide::http::url::{Host, Url};
+
+pub static SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub static EVENT_HEADER: &str = "X-Webhook-Event";
+pub static DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+// the deliveries sent in one round
+static BATCH_SIZE: i64 = 32;
+
+// a receiver that doesn't answer in time counts as a failed attempt
+static DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// how long the deliveries of a round are claimed, longer than the round can take
+static CLAIM_SECS: i64 = 2 * BATCH_SIZE * DELIVERY_TIMEOUT.as_secs() as i64;
+
+// Exponential backoff between the attempts of a delivery, before it's declared dead.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub base_secs: i64,
+    pub max_secs: i64,
+    pub max_attempts: i32,
+}
+
+impl RetryPolicy {
+    // the wait after the failed attempt number `attempts`, `None` once there are no more
+    pub fn retry_in(&self, attempts: i32) -> Option<i64> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let factor = 2i64.saturating_pow((attempts - 1).max(0) as u32);
+        Some(self.base_secs.saturating_mul(factor).min(self.max_secs))
+    }
+}
+
+// `sha256=<hex>`, the HMAC-SHA256 of the body keyed with the secret of the webhook
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// Checks that every address of the host of a webhook url is a public one, so a webhook can't
+// probe the app itself or its network. Checked on creation and again before each delivery, the
+// host may resolve to another address since.
+pub async fn check_target(url: &Url, allow_private: bool) -> Result<(), &'static str> {
+    if allow_private {
+        return Ok(());
+    }
+    let ips: Vec<IpAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+        Some(Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+        Some(Host::Domain(domain)) => {
+            let port = url.port_or_known_default().unwrap_or(80);
+            (domain, port)
+                .to_socket_addrs()
+                .await
+                .map_err(|_| "the host of the url doesn't resolve")?
+                .map(|addr| addr.ip())
+                .collect()
+        }
+        None => return Err("the url has no host"),
+    };
+    if ips.is_empty() {
+        return Err("the host of the url doesn't resolve");
+    }
+    if !ips.iter().all(is_public) {
+        return Err("the url must only resolve to public addresses");
+    }
+    Ok(())
+}
+
+fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(&ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        // 0.0.0.0/8 and the carrier-grade NAT 100.64.0.0/10
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // the unique local fc00::/7 and the link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+// Sends the due deliveries once and returns how many were attempted. The deliveries are claimed
+// first and each attempt is recorded on its own, no transaction is held open while sending.
+pub async fn dispatch(
+    db_pool: &PgPool,
+    client: &surf::Client,
+    policy: &RetryPolicy,
+    allow_private_targets: bool,
+) -> tide::Result<usize> {
+    let due = webhook::claim(BATCH_SIZE, CLAIM_SECS, db_pool).await?;
+    for delivery in &due {
+        match send(client, delivery, allow_private_targets).await {
+            Ok(status) => webhook::delivered(delivery.id, status, db_pool).await?,
+            Err((status, error)) => {
+                let retry_in = policy.retry_in(delivery.attempts + 1);
+                webhook::failed(delivery.id, status, &error, retry_in, db_pool).await?
+            }
+        }
+    }
+
+    Ok(due.len())
+}
+
+// the status of the response, or the one of a failure with why; the log of the deliveries is
+// shown to the owner of the webhook, so the errors of the request itself aren't part of it
+async fn send(
+    client: &surf::Client,
+    delivery: &PendingDelivery,
+    allow_private_targets: bool,
+) -> Result<i32, (Option<i32>, String)> {
+    let url = Url::parse(&delivery.url).map_err(|_| (None, "invalid url".to_string()))?;
+    check_target(&url, allow_private_targets)
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let body = serde_json::to_vec(&json!({
+        "id": delivery.id,
+        "event": delivery.event,
+        "created_at": delivery.created_at,
+        "dino": delivery.dino,
+    }))
+    .map_err(|e| (None, e.to_string()))?;
+
+    let req = client
+        .post(url)
+        .header(SIGNATURE_HEADER, sign(&delivery.secret, &body))
+        .header(EVENT_HEADER, delivery.event.as_str())
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .content_type(tide::http::mime::JSON)
+        .body_bytes(body);
+    let res = async_std::future::timeout(DELIVERY_TIMEOUT, req)
+        .await
+        .map_err(|_| (None, "timed out".to_string()))?
+        .map_err(|e| {
+            tide::log::warn!("webhook delivery failed", { delivery: delivery.id.to_string(), error: e.to_string() });
+            (None, "the request failed".to_string())
+        })?;
+
+    let status = u16::from(res.status()) as i32;
+    if res.status().is_success() {
+        Ok(status)
+    } else {
+        Err((Some(status), format!("the receiver answered {}", status)))
+    }
+}
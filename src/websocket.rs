@@ -0,0 +1,98 @@
+use super::*;
+
+use async_tungstenite::tungstenite::protocol::Role;
+use async_tungstenite::WebSocketStream;
+use std::future::Future;
+use tide::http::headers::{CONNECTION, ORIGIN, UPGRADE};
+use tide::http::upgrade::Connection;
+use tide::http::Url;
+use tide::{Request, Response};
+use tungstenite::handshake::derive_accept_key;
+
+pub type WebSocket = WebSocketStream<Connection>;
+
+// Upgrades the request to a websocket and hands it to `handler` once the 101 is sent.
+pub fn websocket<F>(handler: F) -> WebSocketEndpoint<F> {
+    WebSocketEndpoint { handler }
+}
+
+#[derive(Debug)]
+pub struct WebSocketEndpoint<F> {
+    handler: F,
+}
+
+#[tide::utils::async_trait]
+impl<State, F, Fut> Endpoint<State> for WebSocketEndpoint<F>
+where
+    State: Clone + Send + Sync + 'static,
+    F: Fn(Request<State>, WebSocket) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = tide::Result<()>> + Send + 'static,
+{
+    async fn call(&self, req: Request<State>) -> tide::Result {
+        let upgrade = req
+            .header(UPGRADE)
+            .map(|h| h.as_str().eq_ignore_ascii_case("websocket"))
+            .unwrap_or(false);
+        let connection = req
+            .header(CONNECTION)
+            .map(|h| {
+                h.as_str()
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+            })
+            .unwrap_or(false);
+        let key = match req.header("Sec-WebSocket-Key") {
+            Some(key) if upgrade && connection => key.as_str().to_string(),
+            _ => {
+                let mut res = Response::new(426);
+                res.insert_header(UPGRADE, "websocket");
+                res.set_body("this endpoint only speaks websocket");
+                return Ok(res);
+            }
+        };
+
+        if !same_origin(&req) {
+            return Err(tide::Error::from_str(
+                403,
+                "the websocket only accepts the origin of the app",
+            ));
+        }
+
+        let mut res = Response::new(101);
+        res.insert_header(UPGRADE, "websocket");
+        res.insert_header(CONNECTION, "Upgrade");
+        res.insert_header("Sec-WebSocket-Accept", derive_accept_key(key.as_bytes()));
+
+        let upgrade = AsMut::<tide::http::Response>::as_mut(&mut res)
+            .recv_upgrade()
+            .await;
+        let handler = self.handler.clone();
+        async_std::task::spawn(async move {
+            if let Some(conn) = upgrade.await {
+                let socket = WebSocketStream::from_raw_socket(conn, Role::Server, None).await;
+                if let Err(e) = handler(req, socket).await {
+                    tide::log::error!("websocket failed", { error: e.to_string() });
+                }
+            }
+        });
+
+        Ok(res)
+    }
+}
+
+// Browsers let the pages of any site open a websocket with the cookies of the user, but always
+// say which site it is in the `Origin`; clients outside a browser don't send one.
+fn same_origin<State>(req: &Request<State>) -> bool {
+    let origin = match req.header(ORIGIN) {
+        None => return true,
+        Some(origin) => origin.as_str(),
+    };
+    match Url::parse(origin) {
+        Ok(origin) => {
+            origin.host_str().is_some()
+                && origin.host_str() == req.url().host_str()
+                && origin.port() == req.url().port()
+        }
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::TestDb;
+use tide::prelude::*;
+use tide_basic_crud::{cli, handlers, import, seed};
+use uuid::Uuid;
+
+#[async_std::test]
+async fn admin_commands() -> tide::Result<()> {
+    use cli::{Cli, Command};
+
+    let args = |line: &str| {
+        line.split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(Command::Serve, Cli::parse(args("")).unwrap().command);
+    assert_eq!(
+        Cli {
+            command: Command::Import {
+                path: "dinos.csv".to_string(),
+                format: Some(import::ImportFormat::Ndjson),
+                upsert: true,
+                dry_run: false,
+            },
+            json: true,
+        },
+        Cli::parse(args("--json import dinos.csv --upsert --format ndjson")).unwrap()
+    );
+    assert!(Cli::parse(args("export --format xml")).is_err());
+    assert!(Cli::parse(args("user delete someone")).is_err());
+
+    let db = TestDb::new().await;
+    let id = format!("cli-{}", Uuid::new_v4());
+
+    let command = Cli::parse(args(&format!("user create {}", id)))
+        .unwrap()
+        .command;
+    let created = cli::run(command, &db.db_pool).await?;
+    assert_eq!("user", created.json["role"]);
+    let promoted = cli::run(Command::UserPromote { id: id.clone() }, &db.db_pool).await?;
+    assert_eq!("admin", promoted.json["role"]);
+
+    handlers::session::store(
+        &format!("{}-session", id),
+        Some(id.clone()),
+        json!({}),
+        Some(chrono::Utc::now() + chrono::Duration::hours(1)),
+        &db.db_pool,
+    )
+    .await?;
+    let disabled = cli::run(Command::UserDisable { id: id.clone() }, &db.db_pool).await?;
+    assert_eq!(true, disabled.json["user"]["disabled"]);
+    assert_eq!(1, disabled.json["sessions_purged"]);
+
+    let missing = cli::run(
+        Command::UserPromote {
+            id: "nobody".into(),
+        },
+        &db.db_pool,
+    )
+    .await;
+    assert_eq!(Some(404), missing.err().map(|e| e.status() as u16));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn seed_dinosaur_species() -> tide::Result<()> {
+    let db = TestDb::new().await;
+    let species = seed::species().await?;
+    assert!(species.len() >= 20);
+    assert!(species.iter().any(|dino| dino.name == "Tyrannosaurus"));
+
+    let report = seed::run(&Default::default(), &db.db_pool).await?;
+    assert_eq!((species.len(), 0), (report.created, report.skipped));
+
+    // seeding again creates nothing, and doesn't bring back a deleted species
+    let deleted = &species[0];
+    handlers::dino::delete(deleted.id, &Default::default(), &db.db_pool).await?;
+    let report = seed::run(&Default::default(), &db.db_pool).await?;
+    assert_eq!((0, species.len()), (report.created, report.skipped));
+    assert!(handlers::dino::get(deleted.id, &db.db_pool)
+        .await?
+        .is_none());
+    Ok(())
+}
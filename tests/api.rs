@@ -0,0 +1,276 @@
+mod common;
+
+use common::{fixtures, TestApp};
+use tide_basic_crud::{api, handlers, openapi, telemetry, Config, Dino};
+use uuid::Uuid;
+
+#[async_std::test]
+async fn list_dinos() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let dino = fixtures::dino()
+        .name("test_list")
+        .insert(test.db_pool())
+        .await?;
+    let other = fixtures::dino()
+        .name("test_list")
+        .insert(test.db_pool())
+        .await?;
+    fixtures::dino()
+        .name("test_list_trashed")
+        .deleted_days_ago(1.0)
+        .insert(test.db_pool())
+        .await?;
+
+    let mut res = test
+        .client()
+        .get("https://example.com/api/v1/dinos")
+        .await?;
+
+    assert_eq!(200, res.status());
+    let dinos: Vec<Dino> = res.body_json().await?;
+    let mut ids: Vec<_> = dinos.iter().map(|d| d.id).collect();
+    ids.sort();
+    let mut expected = vec![dino.id, other.id];
+    expected.sort();
+    assert_eq!(expected, ids);
+    Ok(())
+}
+
+#[async_std::test]
+async fn list_dinos_negotiates_the_content_type() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let content_type = |res: &surf::Response| res.content_type().map(|m| m.essence().to_string());
+    for (accept, expected) in [
+        ("*/*", "application/json"),
+        ("application/json", "application/json"),
+        ("text/html,application/xhtml+xml,*/*;q=0.8", "text/html"),
+        ("text/csv;q=0.5, text/html;q=0.9", "text/html"),
+        ("text/*", "text/html"),
+        ("application/xml, text/csv", "text/csv"),
+    ] {
+        let res = client
+            .get("https://example.com/api/v1/dinos")
+            .header("Accept", accept)
+            .await?;
+        assert_eq!(200, res.status(), "{}", accept);
+        assert_eq!(Some(expected.to_string()), content_type(&res), "{}", accept);
+        assert_eq!("Accept", res["Vary"].as_str());
+    }
+
+    let mut res = client
+        .get("https://example.com/api/v1/dinos")
+        .header("Accept", "text/csv")
+        .await?;
+    assert!(res
+        .body_string()
+        .await?
+        .starts_with("id,name,weight,diet,user_id\n"));
+
+    let mut res = client
+        .get("https://example.com/api/v1/dinos")
+        .header("Accept", "application/xml, application/json;q=0")
+        .await?;
+    assert_eq!(406, res.status());
+    let body: serde_json::Value = res.body_json().await?;
+    assert_eq!(
+        serde_json::json!(["application/json", "text/html", "text/csv"]),
+        body["acceptable"]
+    );
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn metrics() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let res = client.get("https://example.com/api/v1/dinos").await?;
+    assert_eq!(200, res.status());
+    for path in [
+        "/api/v1/dinos/not-a-uuid",
+        "/api/v1/dinos/no-such-dino",
+        "/no/such/path-1",
+    ] {
+        client.get(format!("https://example.com{}", path)).await?;
+    }
+
+    let mut res = client.get("https://example.com/metrics").await?;
+    assert_eq!(200, res.status());
+
+    let body = res.body_string().await?;
+    assert!(
+        body.contains(r#"http_requests_total{method="GET",route="/api/v1/dinos",status="200"}"#)
+    );
+    // the requests are labelled with their route, not their path
+    assert!(body
+        .contains(r#"http_requests_total{method="GET",route="/api/v1/dinos/:id",status="400"}"#));
+    assert!(body.contains(r#"route="unmatched",status="404"}"#));
+    assert!(!body.contains("not-a-uuid"));
+    assert!(!body.contains("path-1"));
+    assert!(body.contains(r#"db_query_duration_seconds_count{query="dino_list"}"#));
+    assert!(body.contains("db_pool_connections"));
+    assert!(body.contains("sessions_active"));
+    Ok(())
+}
+
+#[async_std::test]
+async fn metrics_require_the_token() -> tide::Result<()> {
+    let config = Config {
+        metrics_token: Some("secret".to_string()),
+        ..common::config()
+    };
+    let test = TestApp::with_config(config).await;
+    let client = test.client();
+
+    for (authorization, status) in [
+        (None, 401),
+        (Some("Bearer secre"), 401),
+        (Some("Bearer secret!"), 401),
+        (Some("Bearer secret"), 200),
+    ] {
+        let mut req = client.get("https://example.com/metrics");
+        if let Some(authorization) = authorization {
+            req = req.header("Authorization", authorization);
+        }
+        assert_eq!(status, req.await?.status(), "{:?}", authorization);
+    }
+
+    // or an admin
+    handlers::user::create("test_admin", None, handlers::user::ADMIN, test.db_pool()).await?;
+    for (user, status) in [("test_user", 401), ("test_admin", 200)] {
+        let res = test
+            .login(user)
+            .await?
+            .get("https://example.com/metrics")
+            .await?;
+        assert_eq!(status, res.status(), "{}", user);
+    }
+    Ok(())
+}
+
+#[async_std::test]
+async fn request_id_is_propagated() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let res = client
+        .get("https://example.com/api/v1/dinos")
+        .header("X-Request-Id", "test-request-id")
+        .await?;
+    assert_eq!(
+        "test-request-id",
+        res.header("X-Request-Id").unwrap().as_str()
+    );
+
+    // invalid ids are replaced by a generated one
+    let res = client
+        .get("https://example.com/api/v1/dinos")
+        .header("X-Request-Id", "bad id\n")
+        .await?;
+    let id = res.header("X-Request-Id").unwrap().as_str();
+    assert!(Uuid::parse_str(id).is_ok());
+
+    let res = client.get("https://example.com/api/v1/dinos").await?;
+    let id = res.header("X-Request-Id").unwrap().as_str();
+    assert!(Uuid::parse_str(id).is_ok());
+    Ok(())
+}
+
+#[async_std::test]
+async fn traces_are_exported() -> tide::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    // in-process stand-in for an OTLP collector
+    let received: Arc<Mutex<Vec<String>>> = Default::default();
+    let mut collector = tide::with_state(received.clone());
+    collector.at("/v1/traces").post(
+        |mut req: tide::Request<Arc<Mutex<Vec<String>>>>| async move {
+            let body = req.body_string().await?;
+            req.state().lock().unwrap().push(body);
+            Ok(tide::Response::new(200))
+        },
+    );
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let endpoint = format!("http://{}/v1/traces", listener.local_addr()?);
+    async_std::task::spawn(collector.listen(listener));
+
+    let provider = telemetry::init(&endpoint)?;
+
+    let test = TestApp::new().await;
+
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let res = test
+        .client()
+        .get("https://example.com/api/v1/dinos")
+        .header(
+            "traceparent",
+            format!("00-{}-00f067aa0ba902b7-01", trace_id),
+        )
+        .await?;
+    assert_eq!(200, res.status());
+
+    provider.force_flush();
+
+    let exported = received.lock().unwrap().join("\n");
+    assert!(exported.contains(trace_id));
+    assert!(exported.contains("GET /api/v1/dinos"));
+    assert!(exported.contains("dino::list"));
+    assert!(exported.contains("dino_list"));
+    Ok(())
+}
+
+#[test]
+fn every_api_route_has_a_spec_entry() {
+    let mut gen = schemars::gen::SchemaSettings::openapi3().into_generator();
+    for (method, path, _) in api::v1::routes() {
+        assert!(
+            openapi::operation(method, path, &mut gen).is_some(),
+            "missing OpenAPI spec entry for {} {}",
+            method,
+            path
+        );
+    }
+}
+
+#[async_std::test]
+async fn openapi_document() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let mut res = client.get("https://example.com/openapi.json").await?;
+    assert_eq!(200, res.status());
+
+    let doc: serde_json::Value = res.body_json().await?;
+    assert_eq!("3.0.3", doc["openapi"]);
+    assert_eq!("/api/v1", doc["servers"][0]["url"]);
+    assert!(doc["paths"]["/dinos"]["post"].is_object());
+    assert!(doc["paths"]["/dinos/{id}"]["delete"].is_object());
+    assert!(doc["components"]["schemas"]["Dino"]["properties"]["weight"].is_object());
+
+    let res = client.get("https://example.com/docs").await?;
+    assert_eq!(200, res.status());
+    Ok(())
+}
+
+#[async_std::test]
+async fn unversioned_paths_are_deprecated_aliases() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let res = client.get("https://example.com/dinos").await?;
+    assert_eq!(200, res.status());
+    assert_eq!("true", res.header("Deprecation").unwrap().as_str());
+    assert!(res.header("Sunset").is_some());
+    assert_eq!(
+        r#"</api/v1/dinos>; rel="successor-version""#,
+        res.header("Link").unwrap().as_str()
+    );
+
+    let res = client.get("https://example.com/api/v1/dinos").await?;
+    assert_eq!(200, res.status());
+    assert!(res.header("Deprecation").is_none());
+    Ok(())
+}
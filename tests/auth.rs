@@ -0,0 +1,35 @@
+mod common;
+
+use common::TestApp;
+use tide_basic_crud::cli;
+
+#[async_std::test]
+async fn login_and_logout() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let client = test.login("123").await?;
+    let mut res = client.get("https://example.com/").await?;
+    assert_eq!(200, res.status());
+    assert!(res.body_string().await?.contains("Welcome, user 123"));
+
+    let res = client.get("https://example.com/logout").await?;
+    assert_eq!(302, res.status());
+    let mut res = client.get("https://example.com/").await?;
+    assert!(!res.body_string().await?.contains("Welcome"));
+
+    // a disabled user can't log in again
+    cli::run(
+        cli::Command::UserDisable {
+            id: "123".to_string(),
+        },
+        test.db_pool(),
+    )
+    .await?;
+    let res = test
+        .client()
+        .get("https://example.com/auth/google/authorized?code=123&state=test&scope=test")
+        .await?;
+    assert_eq!(403, res.status());
+
+    Ok(())
+}
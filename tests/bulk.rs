@@ -0,0 +1,284 @@
+mod common;
+
+use common::{fixtures, TestApp};
+use tide_basic_crud::{handlers, Dino};
+use uuid::Uuid;
+
+#[async_std::test]
+async fn batch_dinos() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    // create the dinos for the batch
+    let dino = fixtures::dino()
+        .name("test_batch")
+        .insert(test.db_pool())
+        .await?;
+    let owned = fixtures::dino()
+        .name("test_batch_owned")
+        .owner("123")
+        .insert(test.db_pool())
+        .await?;
+
+    let client = test.client();
+
+    let new_id = Uuid::new_v4();
+    let mut updated = dino.clone();
+    updated.weight = 600;
+
+    // one of the operations fails, nothing is applied
+    let mut res = client
+        .post("https://example.com/api/v1/dinos/batch")
+        .body(serde_json::json!({
+            "operations": [
+                { "op": "create", "dino": { "id": new_id, "name": "test_batch_new", "weight": 5, "diet": "herbivorous" } },
+                { "op": "update", "id": dino.id, "dino": updated },
+                { "op": "delete", "id": owned.id },
+            ]
+        }))
+        .await?;
+    assert_eq!(409, res.status());
+    let body: serde_json::Value = res.body_json().await?;
+    assert_eq!(false, body["committed"]);
+    assert_eq!(424, body["results"][0]["status"]);
+    assert_eq!(424, body["results"][1]["status"]);
+    assert_eq!(401, body["results"][2]["status"]);
+    assert!(handlers::dino::get(new_id, test.db_pool()).await?.is_none());
+    assert_eq!(
+        500,
+        handlers::dino::get(dino.id, test.db_pool())
+            .await?
+            .unwrap()
+            .weight
+    );
+
+    // the same batch applied per item
+    let mut res = client
+        .post("https://example.com/api/v1/dinos/batch")
+        .body(serde_json::json!({
+            "atomic": false,
+            "operations": [
+                { "op": "create", "dino": { "id": new_id, "name": "test_batch_new", "weight": 5, "diet": "herbivorous" } },
+                { "op": "update", "id": dino.id, "dino": updated },
+                { "op": "delete", "id": owned.id },
+            ]
+        }))
+        .await?;
+    assert_eq!(200, res.status());
+    let body: serde_json::Value = res.body_json().await?;
+    assert_eq!(true, body["committed"]);
+    assert_eq!(201, body["results"][0]["status"]);
+    assert_eq!(200, body["results"][1]["status"]);
+    assert_eq!(600, body["results"][1]["dino"]["weight"]);
+    assert_eq!(401, body["results"][2]["status"]);
+    assert!(body["results"][2]["error"].is_string());
+    assert!(handlers::dino::get(new_id, test.db_pool()).await?.is_some());
+
+    // an atomic batch that succeeds
+    let mut res = client
+        .post("https://example.com/api/v1/dinos/batch")
+        .body(serde_json::json!({
+            "operations": [
+                { "op": "delete", "id": new_id },
+                { "op": "delete", "id": dino.id },
+            ]
+        }))
+        .await?;
+    assert_eq!(200, res.status());
+    let body: serde_json::Value = res.body_json().await?;
+    assert_eq!(true, body["committed"]);
+    assert_eq!(204, body["results"][0]["status"]);
+    assert_eq!(204, body["results"][1]["status"]);
+    assert!(handlers::dino::get(dino.id, test.db_pool())
+        .await?
+        .is_none());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn batch_dinos_too_many_operations() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let operations: Vec<_> = (0..1001)
+        .map(|_| serde_json::json!({ "op": "delete", "id": Uuid::new_v4() }))
+        .collect();
+    let res = test
+        .client()
+        .post("https://example.com/api/v1/dinos/batch")
+        .body(serde_json::json!({ "operations": operations }))
+        .await?;
+
+    assert_eq!(413, res.status());
+    Ok(())
+}
+
+#[async_std::test]
+async fn import_dinos_from_csv() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let dino = fixtures::dino()
+        .name("test_import")
+        .insert(test.db_pool())
+        .await?;
+
+    let client = test.client();
+
+    let new_id = Uuid::new_v4();
+    let csv = format!(
+        "Diet,Species,Mass (kg),id\n\
+         herbivorous,\"test_import, \"\"new\"\"\nline\",5,{}\n\
+         carnivorous,test_import_updated,600,{}\n",
+        new_id, dino.id
+    );
+    let url =
+        "https://example.com/api/v1/dinos/import?mapping[name]=Species&mapping[weight]=Mass%20(kg)";
+
+    // the second row already exists
+    let mut res = client
+        .post(url)
+        .body(csv.clone())
+        .content_type("text/csv")
+        .await?;
+    assert_eq!(422, res.status());
+    let report: serde_json::Value = res.body_json().await?;
+    assert_eq!(false, report["committed"]);
+    assert_eq!(1, report["created"]);
+    assert_eq!(2, report["errors"][0]["row"]);
+    assert!(handlers::dino::get(new_id, test.db_pool()).await?.is_none());
+
+    // a dry run with upsert validates every row but saves nothing
+    let mut res = client
+        .post(format!("{}&upsert=true&dry_run=true", url))
+        .body(csv.clone())
+        .content_type("text/csv")
+        .await?;
+    assert_eq!(200, res.status());
+    let report: serde_json::Value = res.body_json().await?;
+    assert_eq!(true, report["dry_run"]);
+    assert_eq!(false, report["committed"]);
+    assert_eq!(1, report["created"]);
+    assert_eq!(1, report["updated"]);
+    assert!(handlers::dino::get(new_id, test.db_pool()).await?.is_none());
+
+    let mut res = client
+        .post(format!("{}&upsert=true", url))
+        .body(csv)
+        .content_type("text/csv")
+        .await?;
+    assert_eq!(200, res.status());
+    let report: serde_json::Value = res.body_json().await?;
+    assert_eq!(true, report["committed"]);
+
+    let created = handlers::dino::get(new_id, test.db_pool()).await?.unwrap();
+    assert_eq!("test_import, \"new\"\nline", created.name);
+    assert_eq!(5, created.weight);
+    let updated = handlers::dino::get(dino.id, test.db_pool()).await?.unwrap();
+    assert_eq!("test_import_updated", updated.name);
+    assert_eq!(600, updated.weight);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn import_dinos_from_ndjson() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let id = Uuid::new_v4();
+    let ndjson = format!(
+        "{}\n\n{}\n{}\n",
+        serde_json::json!({ "id": id, "name": "test_import_ndjson", "weight": 5, "diet": "herbivorous" }),
+        serde_json::json!({ "name": "test_import_ndjson", "weight": "heavy", "diet": "herbivorous" }),
+        serde_json::json!({ "name": "test_import_ndjson", "weight": 5, "diet": "herbivorous" }),
+    );
+
+    // per row errors
+    let mut res = client
+        .post("https://example.com/api/v1/dinos/import")
+        .body(ndjson)
+        .content_type("application/x-ndjson")
+        .await?;
+    assert_eq!(422, res.status());
+    let report: serde_json::Value = res.body_json().await?;
+    assert_eq!(2, report["created"]);
+    assert_eq!(1, report["errors"].as_array().unwrap().len());
+    assert_eq!(2, report["errors"][0]["row"]);
+    assert!(handlers::dino::get(id, test.db_pool()).await?.is_none());
+
+    // the format can also be given in the query
+    let res = client
+        .post("https://example.com/api/v1/dinos/import?format=ndjson")
+        .body(serde_json::json!({ "id": id, "name": "test_import_ndjson", "weight": 5, "diet": "herbivorous" }).to_string())
+        .content_type("text/plain")
+        .await?;
+    assert_eq!(200, res.status());
+    assert!(handlers::dino::get(id, test.db_pool()).await?.is_some());
+
+    // unknown format
+    let res = client
+        .post("https://example.com/api/v1/dinos/import")
+        .body("id,name")
+        .content_type("text/plain")
+        .await?;
+    assert_eq!(415, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn export_dinos() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let dino = fixtures::dino()
+        .name("test_export, \"quoted\"")
+        .insert(test.db_pool())
+        .await?;
+
+    let client = test.client();
+
+    let mut res = client
+        .get("https://example.com/api/v1/dinos/export")
+        .await?;
+    assert_eq!(200, res.status());
+    assert_eq!(
+        "attachment; filename=\"dinos.json\"",
+        res["Content-Disposition"].as_str()
+    );
+    let dinos: Vec<Dino> = res.body_json().await?;
+    assert!(dinos.iter().any(|d| d.id == dino.id));
+
+    let mut res = client
+        .get("https://example.com/api/v1/dinos/export?format=ndjson")
+        .await?;
+    assert_eq!(200, res.status());
+    assert_eq!(
+        Some("application/x-ndjson"),
+        res.content_type().as_ref().map(|m| m.essence())
+    );
+    let body = res.body_string().await?;
+    let dinos = body
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Dino>, _>>()?;
+    assert!(dinos.iter().any(|d| d.id == dino.id));
+
+    let mut res = client
+        .get("https://example.com/api/v1/dinos/export?format=csv")
+        .await?;
+    assert_eq!(200, res.status());
+    assert_eq!(
+        "attachment; filename=\"dinos.csv\"",
+        res["Content-Disposition"].as_str()
+    );
+    let body = res.body_string().await?;
+    assert!(body.starts_with("id,name,weight,diet,user_id\n"));
+    assert!(body.contains(&format!(
+        "{},\"test_export, \"\"quoted\"\"\",500,carnivorous,\n",
+        dino.id
+    )));
+
+    let res = client
+        .get("https://example.com/api/v1/dinos/export?format=xml")
+        .await?;
+    assert_eq!(400, res.status());
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use sqlx::{query, PgExecutor};
+use tide::Error;
+use tide_basic_crud::Dino;
+use uuid::Uuid;
+
+// A dino for the tests, inserted straight in the table: unlike a create, it records no audit,
+// revision or event.
+#[derive(Debug, Clone)]
+pub struct DinoFixture {
+    dino: Dino,
+    deleted_days_ago: Option<f64>,
+}
+
+pub fn dino() -> DinoFixture {
+    DinoFixture {
+        dino: Dino {
+            id: Uuid::new_v4(),
+            name: String::from("test"),
+            weight: 500,
+            diet: String::from("carnivorous"),
+            user_id: None,
+        },
+        deleted_days_ago: None,
+    }
+}
+
+impl DinoFixture {
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.dino.id = id;
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.dino.name = name.to_string();
+        self
+    }
+
+    pub fn weight(mut self, weight: i32) -> Self {
+        self.dino.weight = weight;
+        self
+    }
+
+    pub fn diet(mut self, diet: &str) -> Self {
+        self.dino.diet = diet.to_string();
+        self
+    }
+
+    pub fn owner(mut self, user_id: &str) -> Self {
+        self.dino.user_id = Some(user_id.to_string());
+        self
+    }
+
+    // puts the dino in the trash, deleted that many days ago
+    pub fn deleted_days_ago(mut self, days: f64) -> Self {
+        self.deleted_days_ago = Some(days);
+        self
+    }
+
+    pub fn build(&self) -> Dino {
+        self.dino.clone()
+    }
+
+    pub async fn insert<'e, E: PgExecutor<'e>>(self, executor: E) -> tide::Result<Dino> {
+        let dino = self.dino;
+        query!(
+            r#"
+            INSERT INTO dinos (id, name, weight, diet, user_id, deleted_at) VALUES
+            ($1, $2, $3, $4, $5, now() - $6 * interval '1 day')
+            "#,
+            dino.id,
+            dino.name,
+            dino.weight,
+            dino.diet,
+            dino.user_id,
+            self.deleted_days_ago
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::new(409, e))?;
+
+        Ok(dino)
+    }
+}
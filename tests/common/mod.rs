@@ -0,0 +1,178 @@
+// Each test binary uses its own part of these helpers.
+#![allow(dead_code)]
+
+use async_std::sync::Mutex;
+use lazy_static::lazy_static;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+use std::convert::TryInto;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tide::Server;
+use tide_basic_crud::oauth::{AuthProvider, UserInfo};
+use tide_basic_crud::{Config, State};
+use uuid::Uuid;
+
+pub mod fixtures;
+
+lazy_static! {
+    static ref DB_URL: String = {
+        dotenv::dotenv().ok();
+        std::env::var("DATABASE_URL").expect("missing env var DATABASE_URL")
+    };
+    // the extensions are shared by the schemas, they are created once
+    static ref EXTENSIONS: Mutex<bool> = Mutex::new(false);
+}
+
+// Logs in whoever the code names: `/auth/google/authorized?code=123` is user 123.
+#[derive(Debug)]
+pub struct FakeAuthProvider;
+
+#[tide::utils::async_trait]
+impl AuthProvider for FakeAuthProvider {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn authorize_url(&self) -> String {
+        "/auth/google/authorized".to_string()
+    }
+
+    async fn user_info(&self, code: &str) -> tide::Result<UserInfo> {
+        Ok(UserInfo {
+            id: code.to_string(),
+            name: format!("user {}", code),
+        })
+    }
+}
+
+// The settings of the apps under test, none of them read from the environment.
+pub fn config() -> Config {
+    Config {
+        tide_secret: "abcdefghabcdefghabcdefghabcdefgh".to_string(),
+        metrics_token: None,
+        metrics_port: None,
+        idempotency_ttl_secs: 24 * 60 * 60,
+        batch_max_size: 1000,
+        webhook_allow_private_targets: false,
+        auth: Arc::new(FakeAuthProvider),
+    }
+}
+
+// A fresh Postgres schema with the migrations applied, dropped with it.
+pub struct TestDb {
+    pub db_pool: PgPool,
+    schema: String,
+}
+
+impl TestDb {
+    pub async fn new() -> TestDb {
+        let admin_pool = PgPool::connect(&DB_URL).await.unwrap();
+        let mut extensions = EXTENSIONS.lock().await;
+        if !*extensions {
+            sqlx::query("CREATE EXTENSION IF NOT EXISTS pgcrypto WITH SCHEMA public")
+                .execute(&admin_pool)
+                .await
+                .unwrap();
+            *extensions = true;
+        }
+        drop(extensions);
+
+        let schema = format!("test_{}", Uuid::new_v4().to_simple());
+        sqlx::query(&format!("CREATE SCHEMA {}", schema))
+            .execute(&admin_pool)
+            .await
+            .unwrap();
+        admin_pool.close().await;
+
+        let options = PgConnectOptions::from_str(&DB_URL)
+            .unwrap()
+            .options([("search_path", format!("{},public", schema))]);
+        let db_pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations/postgres")
+            .run(&db_pool)
+            .await
+            .unwrap();
+
+        TestDb { db_pool, schema }
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let db_pool = self.db_pool.clone();
+        let schema = self.schema.clone();
+        async_std::task::block_on(async move {
+            // a connection still held by a request left running is given a moment
+            async_std::future::timeout(Duration::from_secs(5), db_pool.close())
+                .await
+                .ok();
+            let admin_pool = PgPool::connect(&DB_URL).await.unwrap();
+            sqlx::query(&format!("DROP SCHEMA {} CASCADE", schema))
+                .execute(&admin_pool)
+                .await
+                .unwrap();
+            admin_pool.close().await;
+        });
+    }
+}
+
+// The app on a fresh database, see `TestDb`.
+pub struct TestApp {
+    pub app: Server<State>,
+    pub db: TestDb,
+}
+
+impl TestApp {
+    pub async fn new() -> TestApp {
+        TestApp::with_config(config()).await
+    }
+
+    pub async fn with_config(config: Config) -> TestApp {
+        let db = TestDb::new().await;
+        let app = tide_basic_crud::server(db.db_pool.clone(), config).await;
+        TestApp { app, db }
+    }
+
+    pub fn db_pool(&self) -> &PgPool {
+        &self.db.db_pool
+    }
+
+    // Another instance of the app on the same database.
+    pub async fn instance(&self) -> Server<State> {
+        tide_basic_crud::server(self.db_pool().clone(), config()).await
+    }
+
+    // A client of the app that isn't logged in.
+    pub fn client(&self) -> surf::Client {
+        surf::Client::with_http_client(self.app.clone())
+    }
+
+    // A client of the app logged in as `user_id`, through the login of `FakeAuthProvider`.
+    pub async fn login(&self, user_id: &str) -> tide::Result<surf::Client> {
+        let res = self
+            .client()
+            .get(format!(
+                "https://example.com/auth/google/authorized?code={}&state=test&scope=test",
+                user_id
+            ))
+            .await?;
+        assert_eq!(302, res.status(), "login as {} failed", user_id);
+        let cookie = res
+            .header("Set-Cookie")
+            .and_then(|cookie| cookie.as_str().split(';').next())
+            .expect("no session cookie")
+            .to_string();
+
+        let client = surf::Config::new()
+            .set_http_client(self.app.clone())
+            .add_header("Cookie", cookie)?
+            .try_into()?;
+        Ok(client)
+    }
+}
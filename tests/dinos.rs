@@ -0,0 +1,494 @@
+mod common;
+
+use common::{fixtures, TestApp};
+use tide_basic_crud::Dino;
+use uuid::Uuid;
+
+#[async_std::test]
+async fn create_dino() -> tide::Result<()> {
+    use assert_json_diff::assert_json_eq;
+
+    let dino = fixtures::dino().weight(50).build();
+
+    let test = TestApp::new().await;
+
+    let mut res = test
+        .client()
+        .post("https://example.com/api/v1/dinos")
+        .body(serde_json::to_string(&dino)?)
+        .await?;
+
+    assert_eq!(201, res.status());
+
+    let d: Dino = res.body_json().await?;
+    assert_json_eq!(dino, d);
+    Ok(())
+}
+
+#[async_std::test]
+async fn create_dino_without_id() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let mut res = test
+        .client()
+        .post("https://example.com/api/v1/dinos")
+        .body(r#"{ "name": "test_generated_id", "weight": 50, "diet": "carnivorous" }"#)
+        .await?;
+
+    assert_eq!(201, res.status());
+
+    let d: Dino = res.body_json().await?;
+    assert_eq!(
+        format!("/api/v1/dinos/{}", d.id),
+        res.header("Location").unwrap().as_str()
+    );
+    assert_eq!("test_generated_id", d.name);
+    Ok(())
+}
+
+#[async_std::test]
+async fn create_dino_with_idempotency_key() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.login("123").await?;
+
+    let key = Uuid::new_v4().to_string();
+    let body = r#"{ "name": "test_idempotent", "weight": 50, "diet": "carnivorous" }"#;
+
+    let mut res = client
+        .post("https://example.com/api/v1/dinos")
+        .header("Idempotency-Key", key.as_str())
+        .body(body)
+        .await?;
+    assert_eq!(201, res.status());
+    assert!(res.header("Idempotent-Replayed").is_none());
+    let created: Dino = res.body_json().await?;
+
+    // the retry gets the same dino back instead of creating another one
+    let mut res = client
+        .post("https://example.com/api/v1/dinos")
+        .header("Idempotency-Key", key.as_str())
+        .body(body)
+        .await?;
+    assert_eq!(201, res.status());
+    assert_eq!("true", res.header("Idempotent-Replayed").unwrap().as_str());
+    assert_eq!(
+        format!("/api/v1/dinos/{}", created.id),
+        res.header("Location").unwrap().as_str()
+    );
+    let replayed: Dino = res.body_json().await?;
+    assert_eq!(created.id, replayed.id);
+
+    // reusing the key for another request is rejected
+    let res = client
+        .post("https://example.com/api/v1/dinos")
+        .header("Idempotency-Key", key.as_str())
+        .body(r#"{ "name": "other", "weight": 50, "diet": "carnivorous" }"#)
+        .await?;
+    assert_eq!(422, res.status());
+    Ok(())
+}
+
+#[async_std::test]
+async fn idempotency_keys_need_a_user() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let res = test
+        .client()
+        .post("https://example.com/api/v1/dinos")
+        .header("Idempotency-Key", Uuid::new_v4().to_string().as_str())
+        .body(r#"{ "name": "test_idempotent", "weight": 50, "diet": "carnivorous" }"#)
+        .await?;
+    assert_eq!(401, res.status());
+    Ok(())
+}
+
+#[async_std::test]
+async fn idempotency_keys_of_dead_requests_are_freed() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.login("123").await?;
+    let body = r#"{ "name": "test_idempotent", "weight": 50, "diet": "carnivorous" }"#;
+
+    // two reservations never completed, a recent one and one of a request that died
+    for (key, minutes_ago) in [("in-progress", 0.0), ("dead", 5.0)] {
+        sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (key, user_id, request_body, created_at) VALUES
+            ($1, '123', $2, now() - $3 * interval '1 minute')
+            "#,
+        )
+        .bind(key)
+        .bind(body)
+        .bind(minutes_ago)
+        .execute(test.db_pool())
+        .await?;
+    }
+
+    for (key, status) in [("in-progress", 409), ("dead", 201)] {
+        let res = client
+            .post("https://example.com/api/v1/dinos")
+            .header("Idempotency-Key", key)
+            .body(body)
+            .await?;
+        assert_eq!(status, res.status(), "{}", key);
+    }
+    Ok(())
+}
+
+#[async_std::test]
+async fn create_dino_with_existing_key() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    // create the dino
+    let dino = fixtures::dino()
+        .name("test_get")
+        .insert(test.db_pool())
+        .await?;
+
+    let res = test
+        .client()
+        .post("https://example.com/api/v1/dinos")
+        .body(serde_json::to_string(&dino)?)
+        .await?;
+
+    assert_eq!(409, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn get_dino() -> tide::Result<()> {
+    use assert_json_diff::assert_json_eq;
+
+    let test = TestApp::new().await;
+
+    // create the dino for get
+    let dino = fixtures::dino()
+        .name("test_get")
+        .insert(test.db_pool())
+        .await?;
+
+    let mut res = test
+        .client()
+        .get(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .await?;
+
+    assert_eq!(200, res.status());
+
+    let d: Dino = res.body_json().await?;
+    assert_json_eq!(dino, d);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn get_dino_non_existing_key() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let res = test
+        .client()
+        .get(format!(
+            "https://example.com/api/v1/dinos/{}",
+            &Uuid::new_v4()
+        ))
+        .await?;
+
+    assert_eq!(404, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn update_dino() -> tide::Result<()> {
+    use assert_json_diff::assert_json_eq;
+
+    let test = TestApp::new().await;
+
+    // create the dino for update
+    let mut dino = fixtures::dino()
+        .name("test_update")
+        .insert(test.db_pool())
+        .await?;
+
+    // change the dino
+    dino.name = String::from("updated from test");
+
+    let mut res = test
+        .client()
+        .put(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .body(serde_json::to_string(&dino)?)
+        .await?;
+
+    assert_eq!(200, res.status());
+
+    let d: Dino = res.body_json().await?;
+    assert_json_eq!(dino, d);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn updatet_dino_non_existing_key() -> tide::Result<()> {
+    let dino = fixtures::dino().name("test_update").build();
+
+    let test = TestApp::new().await;
+
+    let res = test
+        .client()
+        .put(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .body(serde_json::to_string(&dino)?)
+        .await?;
+
+    assert_eq!(404, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn updatet_dino_create_by_another_user_should_reject_with_401() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    // create the dino for update
+    let mut dino = fixtures::dino()
+        .name("test_update")
+        .owner("123")
+        .insert(test.db_pool())
+        .await?;
+
+    // change the dino
+    dino.name = String::from("updated from test");
+
+    let res = test
+        .client()
+        .put(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .body(serde_json::to_string(&dino)?)
+        .await?;
+
+    assert_eq!(401, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn patch_dino_with_merge_patch() -> tide::Result<()> {
+    use assert_json_diff::assert_json_eq;
+
+    let test = TestApp::new().await;
+
+    // create the dino for patch
+    let mut dino = fixtures::dino()
+        .name("test_patch")
+        .insert(test.db_pool())
+        .await?;
+
+    let mut res = test
+        .client()
+        .patch(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .content_type("application/merge-patch+json")
+        .body(r#"{ "weight": 750 }"#)
+        .await?;
+
+    assert_eq!(200, res.status());
+
+    dino.weight = 750;
+    let d: Dino = res.body_json().await?;
+    assert_json_eq!(dino, d);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn patch_dino_with_json_patch() -> tide::Result<()> {
+    use assert_json_diff::assert_json_eq;
+
+    let test = TestApp::new().await;
+
+    // create the dino for patch
+    let mut dino = fixtures::dino()
+        .name("test_patch")
+        .insert(test.db_pool())
+        .await?;
+
+    let client = test.client();
+
+    let mut res = client
+        .patch(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .content_type("application/json-patch+json")
+        .body(
+            r#"[
+                { "op": "test", "path": "/name", "value": "test_patch" },
+                { "op": "replace", "path": "/diet", "value": "herbivorous" }
+            ]"#,
+        )
+        .await?;
+
+    assert_eq!(200, res.status());
+
+    dino.diet = String::from("herbivorous");
+    let d: Dino = res.body_json().await?;
+    assert_json_eq!(dino, d);
+
+    // a failed test operation doesn't change anything
+    let res = client
+        .patch(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .content_type("application/json-patch+json")
+        .body(
+            r#"[
+                { "op": "test", "path": "/name", "value": "other" },
+                { "op": "replace", "path": "/diet", "value": "omnivorous" }
+            ]"#,
+        )
+        .await?;
+    assert_eq!(409, res.status());
+
+    // the result must still be a valid dino, with the same id
+    let res = client
+        .patch(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .content_type("application/json-patch+json")
+        .body(r#"[{ "op": "remove", "path": "/weight" }]"#)
+        .await?;
+    assert_eq!(422, res.status());
+
+    let res = client
+        .patch(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .content_type("application/merge-patch+json")
+        .body(format!(r#"{{ "id": "{}" }}"#, Uuid::new_v4()))
+        .await?;
+    assert_eq!(422, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn patch_dino_with_unsupported_content_type() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let res = test
+        .client()
+        .patch(format!(
+            "https://example.com/api/v1/dinos/{}",
+            &Uuid::new_v4()
+        ))
+        .content_type("application/json")
+        .body(r#"{ "weight": 750 }"#)
+        .await?;
+
+    assert_eq!(415, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn patch_dino_non_existing_key() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let res = test
+        .client()
+        .patch(format!(
+            "https://example.com/api/v1/dinos/{}",
+            &Uuid::new_v4()
+        ))
+        .content_type("application/merge-patch+json")
+        .body(r#"{ "weight": 750 }"#)
+        .await?;
+
+    assert_eq!(404, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn patch_dino_malformed_id() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let res = test
+        .client()
+        .patch("https://example.com/api/v1/dinos/not-a-uuid")
+        .content_type("application/merge-patch+json")
+        .body(r#"{ "weight": 750 }"#)
+        .await?;
+
+    assert_eq!(400, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn patch_dino_create_by_another_user_should_reject_with_401() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    // create the dino for patch
+    let dino = fixtures::dino()
+        .name("test_patch")
+        .owner("123")
+        .insert(test.db_pool())
+        .await?;
+
+    let res = test
+        .client()
+        .patch(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .content_type("application/merge-patch+json")
+        .body(r#"{ "weight": 750 }"#)
+        .await?;
+
+    assert_eq!(401, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn delete_dino() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    // create the dino for delete
+    let dino = fixtures::dino()
+        .name("test_delete")
+        .insert(test.db_pool())
+        .await?;
+
+    let res = test
+        .client()
+        .delete(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .await?;
+
+    assert_eq!(204, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn delete_dino_non_existing_key() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    let res = test
+        .client()
+        .delete(format!(
+            "https://example.com/api/v1/dinos/{}",
+            &Uuid::new_v4()
+        ))
+        .await?;
+
+    assert_eq!(404, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn delete_dino_create_by_another_user_should_reject_with_401() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    // create the dino for delete
+    let dino = fixtures::dino()
+        .name("test_delete")
+        .owner("123")
+        .insert(test.db_pool())
+        .await?;
+
+    let res = test
+        .client()
+        .delete(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .await?;
+
+    assert_eq!(401, res.status());
+
+    Ok(())
+}
@@ -0,0 +1,352 @@
+mod common;
+
+use common::{fixtures, TestApp};
+use tide_basic_crud::{handlers, Dino, TrashedDino};
+use uuid::Uuid;
+
+#[async_std::test]
+async fn deleted_dinos_go_to_the_trash() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    // the owned dino is already in the trash
+    let dino = fixtures::dino()
+        .name("test_trash")
+        .insert(test.db_pool())
+        .await?;
+    let owned = fixtures::dino()
+        .name("test_trash_owned")
+        .owner("123")
+        .deleted_days_ago(1.0)
+        .insert(test.db_pool())
+        .await?;
+
+    let client = test.client();
+
+    let res = client
+        .delete(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .await?;
+    assert_eq!(204, res.status());
+
+    let res = client
+        .get(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .await?;
+    assert_eq!(404, res.status());
+    let res = client
+        .delete(format!("https://example.com/api/v1/dinos/{}", &dino.id))
+        .await?;
+    assert_eq!(404, res.status());
+
+    // only the dinos the user can restore are listed
+    let mut res = client.get("https://example.com/api/v1/dinos/trash").await?;
+    assert_eq!(200, res.status());
+    let trash: Vec<TrashedDino> = res.body_json().await?;
+    assert!(trash.iter().any(|d| d.id == dino.id));
+    assert!(!trash.iter().any(|d| d.id == owned.id));
+
+    let res = client
+        .post(format!(
+            "https://example.com/api/v1/dinos/{}/restore",
+            &owned.id
+        ))
+        .await?;
+    assert_eq!(401, res.status());
+
+    let mut res = client
+        .post(format!(
+            "https://example.com/api/v1/dinos/{}/restore",
+            &dino.id
+        ))
+        .await?;
+    assert_eq!(200, res.status());
+    let restored: Dino = res.body_json().await?;
+    assert_eq!(dino.name, restored.name);
+    assert!(handlers::dino::get(dino.id, test.db_pool())
+        .await?
+        .is_some());
+
+    let res = client
+        .post(format!(
+            "https://example.com/api/v1/dinos/{}/restore",
+            &dino.id
+        ))
+        .await?;
+    assert_eq!(404, res.status());
+
+    // the trash page
+    handlers::dino::delete(dino.id, &Default::default(), test.db_pool()).await?;
+    let mut res = client.get("https://example.com/dinos/trash").await?;
+    assert_eq!(200, res.status());
+    assert!(res.body_string().await?.contains("test_trash"));
+
+    // only the dinos deleted for longer than the retention are purged
+    assert_eq!(1, handlers::dino::purge(60 * 60, test.db_pool()).await?);
+    assert!(handlers::dino::get_trashed(owned.id, test.db_pool())
+        .await?
+        .is_none());
+    assert!(handlers::dino::get_trashed(dino.id, test.db_pool())
+        .await?
+        .is_some());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn dino_changes_are_audited() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let mut res = client
+        .post("https://example.com/api/v1/dinos")
+        .header("X-Request-Id", "audit-create")
+        .body(serde_json::json!({ "name": "test_audit", "weight": 50, "diet": "carnivorous" }))
+        .await?;
+    assert_eq!(201, res.status());
+    let dino: Dino = res.body_json().await?;
+
+    let res = client
+        .patch(format!("https://example.com/api/v1/dinos/{}", dino.id))
+        .header("X-Request-Id", "audit-update")
+        .body(serde_json::json!({ "weight": 60 }))
+        .content_type("application/merge-patch+json")
+        .await?;
+    assert_eq!(200, res.status());
+
+    // the edit page shows the timeline
+    let mut res = client
+        .get(format!("https://example.com/dinos/{}/edit", dino.id))
+        .await?;
+    assert!(res.body_string().await?.contains("History"));
+
+    let res = client
+        .delete(format!("https://example.com/api/v1/dinos/{}", dino.id))
+        .await?;
+    assert_eq!(204, res.status());
+
+    let mut res = client
+        .get(format!(
+            "https://example.com/api/v1/dinos/{}/history",
+            dino.id
+        ))
+        .await?;
+    assert_eq!(200, res.status());
+    let history: Vec<serde_json::Value> = res.body_json().await?;
+    let actions: Vec<_> = history.iter().map(|r| r["action"].clone()).collect();
+    assert_eq!(
+        serde_json::json!(["create", "update", "delete"]),
+        serde_json::json!(actions)
+    );
+
+    assert_eq!("audit-create", history[0]["request_id"]);
+    assert!(history[0]["before"].is_null());
+    assert_eq!(
+        serde_json::json!({ "from": null, "to": "test_audit" }),
+        history[0]["diff"]["name"]
+    );
+
+    assert_eq!("audit-update", history[1]["request_id"]);
+    assert_eq!(
+        serde_json::json!({ "weight": { "from": 50, "to": 60 } }),
+        history[1]["diff"]
+    );
+
+    assert!(history[2]["after"].is_null());
+    assert_eq!(60, history[2]["before"]["weight"]);
+
+    let res = client
+        .get(format!(
+            "https://example.com/api/v1/dinos/{}/history",
+            Uuid::new_v4()
+        ))
+        .await?;
+    assert_eq!(404, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn revert_dino_to_a_previous_revision() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    // created without revisions
+    let dino = fixtures::dino()
+        .name("test_revert")
+        .insert(test.db_pool())
+        .await?;
+    let owned = fixtures::dino()
+        .name("test_revert_owned")
+        .owner("123")
+        .insert(test.db_pool())
+        .await?;
+    let mut changed = owned.clone();
+    changed.weight = 600;
+    handlers::dino::update(owned.id, changed, &Default::default(), test.db_pool()).await?;
+
+    let client = test.client();
+
+    let mut updated = dino.clone();
+    updated.weight = 600;
+    let res = client
+        .put(format!("https://example.com/api/v1/dinos/{}", dino.id))
+        .body(serde_json::to_value(&updated)?)
+        .await?;
+    assert_eq!(200, res.status());
+    let res = client
+        .patch(format!("https://example.com/api/v1/dinos/{}", dino.id))
+        .body(serde_json::json!({ "weight": 700 }))
+        .content_type("application/merge-patch+json")
+        .await?;
+    assert_eq!(200, res.status());
+
+    let mut res = client
+        .post(format!(
+            "https://example.com/api/v1/dinos/{}/revisions/1/revert",
+            dino.id
+        ))
+        .await?;
+    assert_eq!(200, res.status());
+    let reverted: Dino = res.body_json().await?;
+    assert_eq!(500, reverted.weight);
+
+    let mut res = client
+        .get(format!(
+            "https://example.com/api/v1/dinos/{}/revisions",
+            dino.id
+        ))
+        .await?;
+    assert_eq!(200, res.status());
+    let revisions: Vec<serde_json::Value> = res.body_json().await?;
+    let weights: Vec<_> = revisions.iter().map(|r| r["weight"].clone()).collect();
+    assert_eq!(
+        serde_json::json!([500, 600, 700, 500]),
+        serde_json::json!(weights)
+    );
+    assert_eq!(4, revisions[3]["rev"]);
+
+    // the edit page offers a diff between revisions
+    let mut res = client
+        .get(format!("https://example.com/dinos/{}/edit", dino.id))
+        .await?;
+    assert!(res.body_string().await?.contains("Revisions"));
+
+    let res = client
+        .post(format!(
+            "https://example.com/api/v1/dinos/{}/revisions/9/revert",
+            dino.id
+        ))
+        .await?;
+    assert_eq!(404, res.status());
+
+    let res = client
+        .post(format!(
+            "https://example.com/api/v1/dinos/{}/revisions/1/revert",
+            owned.id
+        ))
+        .await?;
+    assert_eq!(401, res.status());
+    assert_eq!(
+        600,
+        handlers::dino::get(owned.id, test.db_pool())
+            .await?
+            .unwrap()
+            .weight
+    );
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn reverted_dinos_keep_their_owner() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let dino = fixtures::dino()
+        .name("test_revert_owner")
+        .insert(test.db_pool())
+        .await?;
+    // revision 1 has no owner, revision 2 belongs to 123
+    let mut owned = dino.clone();
+    owned.weight = 600;
+    owned.user_id = Some("123".to_string());
+    handlers::dino::update(dino.id, owned, &Default::default(), test.db_pool()).await?;
+
+    let mut res = test
+        .login("123")
+        .await?
+        .post(format!(
+            "https://example.com/api/v1/dinos/{}/revisions/1/revert",
+            dino.id
+        ))
+        .await?;
+    assert_eq!(200, res.status());
+    let reverted: Dino = res.body_json().await?;
+    assert_eq!(500, reverted.weight);
+    assert_eq!(Some("123"), reverted.user_id.as_deref());
+
+    let stored = handlers::dino::get(dino.id, test.db_pool()).await?.unwrap();
+    assert_eq!(Some("123"), stored.user_id.as_deref());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn restore_a_malformed_id() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let res = client
+        .post("https://example.com/api/v1/dinos/not-a-uuid/restore")
+        .await?;
+    assert_eq!(400, res.status());
+
+    let res = client.get("https://example.com/dinos/trash").await?;
+    assert_eq!(200, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn history_of_a_malformed_id() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+
+    let res = client
+        .get("https://example.com/api/v1/dinos/not-a-uuid/history")
+        .await?;
+    assert_eq!(400, res.status());
+
+    let res = client
+        .get("https://example.com/dinos/not-a-uuid/edit")
+        .await?;
+    assert_eq!(400, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn revisions_of_a_malformed_id() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.client();
+    let dino = fixtures::dino()
+        .name("test_revisions")
+        .insert(test.db_pool())
+        .await?;
+
+    let res = client
+        .get("https://example.com/api/v1/dinos/not-a-uuid/revisions")
+        .await?;
+    assert_eq!(400, res.status());
+
+    let res = client
+        .post("https://example.com/api/v1/dinos/not-a-uuid/revisions/1/revert")
+        .await?;
+    assert_eq!(400, res.status());
+
+    for rev in ["latest", "1.5", "99999999999"] {
+        let res = client
+            .post(format!(
+                "https://example.com/api/v1/dinos/{}/revisions/{}/revert",
+                dino.id, rev
+            ))
+            .await?;
+        assert_eq!(400, res.status(), "revision {}", rev);
+    }
+
+    Ok(())
+}
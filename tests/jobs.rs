@@ -0,0 +1,165 @@
+mod common;
+
+use common::TestDb;
+use tide::prelude::*;
+use tide::Error;
+use tide_basic_crud::{handlers, jobs};
+use uuid::Uuid;
+
+#[async_std::test]
+async fn jobs_are_retried_delayed_and_recurring() -> tide::Result<()> {
+    use jobs::{Job, JobContext};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    // fails its first run
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Flaky {
+        run: Uuid,
+    }
+
+    #[tide::utils::async_trait]
+    impl Job for Flaky {
+        const KIND: &'static str = "test_flaky";
+
+        async fn run(self, _cx: &JobContext) -> tide::Result<()> {
+            match RUNS.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(Error::from_str(500, "first run")),
+                _ => Ok(()),
+            }
+        }
+
+        fn retry_in(_attempt: i32) -> std::time::Duration {
+            std::time::Duration::from_secs(0)
+        }
+    }
+
+    let db = TestDb::new().await;
+    let run = Uuid::new_v4();
+    let worker = jobs::Worker::new(db.db_pool.clone()).recurring(
+        &format!("test_flaky_{}", run),
+        "@every 1h".parse().unwrap(),
+        Flaky { run },
+    );
+    worker.start().await?;
+
+    // the recurring job is due right away, and not again before an hour
+    assert_eq!(1, worker.enqueue_recurring().await?);
+    assert_eq!(0, worker.enqueue_recurring().await?);
+
+    let delayed = jobs::enqueue_at(
+        &Flaky { run },
+        chrono::Utc::now() + chrono::Duration::hours(1),
+        &db.db_pool,
+    )
+    .await?;
+    let now = jobs::enqueue(&Flaky { run }, &db.db_pool).await?;
+
+    while worker.run_once().await? {}
+    assert_eq!(3, RUNS.load(Ordering::SeqCst));
+
+    let job = handlers::job::get(now, &db.db_pool).await?.unwrap();
+    assert_eq!("done", job.status);
+    let delayed_job = handlers::job::get(delayed, &db.db_pool).await?.unwrap();
+    assert_eq!(
+        ("pending", 0),
+        (delayed_job.status.as_str(), delayed_job.attempts)
+    );
+    assert_eq!(Some(()), handlers::job::cancel(delayed, &db.db_pool).await?);
+
+    // every 15 minutes of the working hours, on weekdays
+    let schedule: jobs::Schedule = "*/15 9-17 * * 1-5".parse().unwrap();
+    let friday = chrono::DateTime::parse_from_rfc3339("2021-06-04T17:50:00Z")?;
+    let next = schedule.next_after(friday.with_timezone(&chrono::Utc));
+    assert_eq!(
+        Some("2021-06-07T09:00:00+00:00".to_string()),
+        next.map(|next| next.to_rfc3339())
+    );
+    assert!("61 * * * *".parse::<jobs::Schedule>().is_err());
+    for schedule in ["@every 5µ", "@every µ", "@every", "@every 5", "@every 0s"] {
+        assert!(schedule.parse::<jobs::Schedule>().is_err(), "{}", schedule);
+    }
+    assert_eq!(
+        Ok(jobs::Schedule::Every(chrono::Duration::minutes(5))),
+        "@every 5m".parse::<jobs::Schedule>()
+    );
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn recurring_jobs_keep_only_their_failed_runs() -> tide::Result<()> {
+    use jobs::{Job, JobContext};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    // fails its second run
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Tick {}
+
+    #[tide::utils::async_trait]
+    impl Job for Tick {
+        const KIND: &'static str = "test_tick";
+        const MAX_ATTEMPTS: i32 = 1;
+
+        async fn run(self, _cx: &JobContext) -> tide::Result<()> {
+            match RUNS.fetch_add(1, Ordering::SeqCst) {
+                1 => Err(Error::from_str(500, "second run")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    let db = TestDb::new().await;
+    let worker = jobs::Worker::new(db.db_pool.clone()).recurring(
+        "test_tick",
+        "@every 1h".parse().unwrap(),
+        Tick {},
+    );
+    worker.start().await?;
+
+    for _ in 0..4 {
+        sqlx::query("UPDATE recurring_jobs SET next_run_at = now()")
+            .execute(&db.db_pool)
+            .await?;
+        assert_eq!(1, worker.enqueue_recurring().await?);
+        assert!(worker.run_once().await?);
+    }
+    assert_eq!(4, RUNS.load(Ordering::SeqCst));
+
+    let statuses: Vec<(String,)> =
+        sqlx::query_as("SELECT status FROM jobs WHERE kind = 'test_tick' ORDER BY created_at")
+            .fetch_all(&db.db_pool)
+            .await?;
+    let statuses: Vec<_> = statuses.iter().map(|(status,)| status.as_str()).collect();
+    assert_eq!(vec!["failed", "done"], statuses);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn expired_sessions_are_purged() -> tide::Result<()> {
+    let db = TestDb::new().await;
+    sqlx::query(
+        "INSERT INTO sessions (id, session, expires_at) VALUES \
+         ('test_expired', '{}', now() - interval '1 day'), \
+         ('test_live', '{}', now() + interval '1 day')",
+    )
+    .execute(&db.db_pool)
+    .await?;
+
+    // the recurring jobs of the app are all due on start
+    let worker = tide_basic_crud::worker(db.db_pool.clone());
+    worker.start().await?;
+    worker.enqueue_recurring().await?;
+    while worker.run_once().await? {}
+
+    let sessions: Vec<(String,)> = sqlx::query_as("SELECT id FROM sessions")
+        .fetch_all(&db.db_pool)
+        .await?;
+    assert_eq!(vec![("test_live".to_string(),)], sessions);
+
+    Ok(())
+}
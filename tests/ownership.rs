@@ -0,0 +1,271 @@
+mod common;
+
+use common::{fixtures, TestApp};
+use tide::prelude::*;
+use tide_basic_crud::{handlers, BatchResponse, Dino};
+
+const OWNER: &str = "owner-1";
+const OTHER: &str = "other-2";
+
+// (who owns the dino, who acts on it, allowed), anonymous when `None`
+static MATRIX: &[(Option<&str>, Option<&str>, bool)] = &[
+    (Some(OWNER), None, false),
+    (Some(OWNER), Some(OWNER), true),
+    (Some(OWNER), Some(OTHER), false),
+    (None, None, true),
+    (None, Some(OWNER), true),
+    (None, Some(OTHER), true),
+];
+
+async fn client(test: &TestApp, user: Option<&str>) -> tide::Result<surf::Client> {
+    match user {
+        None => Ok(test.client()),
+        Some(user) => test.login(user).await,
+    }
+}
+
+async fn insert(test: &TestApp, owner: Option<&str>) -> tide::Result<Dino> {
+    let dino = fixtures::dino().name("test_ownership");
+    match owner {
+        None => dino.insert(test.db_pool()).await,
+        Some(owner) => dino.owner(owner).insert(test.db_pool()).await,
+    }
+}
+
+#[async_std::test]
+async fn created_dinos_belong_to_the_user() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    for user in [None, Some(OWNER), Some(OTHER)] {
+        // the owner of the body is ignored
+        let mut res = client(&test, user)
+            .await?
+            .post("https://example.com/api/v1/dinos")
+            .body(json!({ "name": "test_ownership", "weight": 50, "diet": "carnivorous", "user_id": "someone" }))
+            .await?;
+        assert_eq!(201, res.status(), "{:?} creating a dino", user);
+        let dino: Dino = res.body_json().await?;
+        assert_eq!(user, dino.user_id.as_deref());
+
+        let stored = handlers::dino::get(dino.id, test.db_pool()).await?.unwrap();
+        assert_eq!(user, stored.user_id.as_deref());
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn only_the_owner_updates_a_dino() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    for &(owner, user, allowed) in MATRIX {
+        let dino = insert(&test, owner).await?;
+        let mut updated = dino.clone();
+        updated.weight = 600;
+
+        let res = client(&test, user)
+            .await?
+            .put(format!("https://example.com/api/v1/dinos/{}", dino.id))
+            .body(serde_json::to_value(&updated)?)
+            .await?;
+        let expected = if allowed { 200 } else { 401 };
+        let case = format!("{:?} updating a dino of {:?}", user, owner);
+        assert_eq!(expected, res.status(), "{}", case);
+
+        let stored = handlers::dino::get(dino.id, test.db_pool()).await?.unwrap();
+        let weight = if allowed { 600 } else { 500 };
+        assert_eq!(weight, stored.weight, "{}", case);
+        assert_eq!(owner, stored.user_id.as_deref(), "{}", case);
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn only_the_owner_updates_a_dino_in_a_batch() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    for &(owner, user, allowed) in MATRIX {
+        let dino = insert(&test, owner).await?;
+
+        // the owner of the body is ignored
+        let mut res = client(&test, user)
+            .await?
+            .post("https://example.com/api/v1/dinos/batch")
+            .body(json!({
+                "atomic": false,
+                "operations": [{
+                    "op": "update",
+                    "id": dino.id,
+                    "dino": { "name": "test_ownership", "weight": 600, "diet": "carnivorous", "user_id": "someone" },
+                }],
+            }))
+            .await?;
+        let case = format!("{:?} updating a dino of {:?} in a batch", user, owner);
+        assert_eq!(200, res.status(), "{}", case);
+        let batch: BatchResponse = res.body_json().await?;
+        let expected = if allowed { 200 } else { 401 };
+        assert_eq!(expected, batch.results[0].status, "{}", case);
+
+        let stored = handlers::dino::get(dino.id, test.db_pool()).await?.unwrap();
+        let weight = if allowed { 600 } else { 500 };
+        assert_eq!(weight, stored.weight, "{}", case);
+        assert_eq!(owner, stored.user_id.as_deref(), "{}", case);
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn only_the_owner_patches_a_dino() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    for &(owner, user, allowed) in MATRIX {
+        let dino = insert(&test, owner).await?;
+
+        let res = client(&test, user)
+            .await?
+            .patch(format!("https://example.com/api/v1/dinos/{}", dino.id))
+            .content_type("application/merge-patch+json")
+            .body(json!({ "weight": 600 }))
+            .await?;
+        let expected = if allowed { 200 } else { 401 };
+        let case = format!("{:?} patching a dino of {:?}", user, owner);
+        assert_eq!(expected, res.status(), "{}", case);
+
+        let stored = handlers::dino::get(dino.id, test.db_pool()).await?.unwrap();
+        let weight = if allowed { 600 } else { 500 };
+        assert_eq!(weight, stored.weight, "{}", case);
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn the_owner_of_a_dino_is_kept() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let client = test.login(OWNER).await?;
+
+    for owner in [None, Some(OWNER)] {
+        let dino = insert(&test, owner).await?;
+        let url = format!("https://example.com/api/v1/dinos/{}", dino.id);
+
+        // the owner of the body is ignored
+        let mut updated = dino.clone();
+        updated.weight = 600;
+        updated.user_id = Some(OTHER.to_string());
+        let res = client
+            .put(&url)
+            .body(serde_json::to_value(&updated)?)
+            .await?;
+        assert_eq!(200, res.status(), "updating a dino of {:?}", owner);
+
+        // (content type, patch, owner after the patch)
+        let patches = [
+            (
+                "application/merge-patch+json",
+                json!({ "user_id": OTHER }),
+                Some(OTHER),
+            ),
+            (
+                "application/merge-patch+json",
+                json!({ "user_id": null }),
+                None,
+            ),
+            (
+                "application/json-patch+json",
+                json!([{ "op": "replace", "path": "/user_id", "value": OTHER }]),
+                Some(OTHER),
+            ),
+        ];
+        for (content_type, patch, patched) in patches {
+            let res = client
+                .patch(&url)
+                .content_type(content_type)
+                .body(patch.clone())
+                .await?;
+            let expected = if patched == owner { 200 } else { 422 };
+            let case = format!("patching a dino of {:?} with {}", owner, patch);
+            assert_eq!(expected, res.status(), "{}", case);
+        }
+
+        let stored = handlers::dino::get(dino.id, test.db_pool()).await?.unwrap();
+        assert_eq!(600, stored.weight);
+        assert_eq!(owner, stored.user_id.as_deref());
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn only_the_owner_deletes_a_dino() -> tide::Result<()> {
+    let test = TestApp::new().await;
+
+    for &(owner, user, allowed) in MATRIX {
+        let dino = insert(&test, owner).await?;
+
+        let res = client(&test, user)
+            .await?
+            .delete(format!("https://example.com/api/v1/dinos/{}", dino.id))
+            .await?;
+        let expected = if allowed { 204 } else { 401 };
+        let case = format!("{:?} deleting a dino of {:?}", user, owner);
+        assert_eq!(expected, res.status(), "{}", case);
+
+        let trashed = handlers::dino::get_trashed(dino.id, test.db_pool()).await?;
+        assert_eq!(allowed, trashed.is_some(), "{}", case);
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn webhooks_belong_to_their_user() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let webhook = json!({ "url": "https://93.184.215.14/hook", "secret": "s3cret" });
+
+    let anonymous = test.client();
+    let res = anonymous
+        .post("https://example.com/api/v1/webhooks")
+        .body(webhook.clone())
+        .await?;
+    assert_eq!(401, res.status());
+    let res = anonymous.get("https://example.com/api/v1/webhooks").await?;
+    assert_eq!(401, res.status());
+
+    // the secret is shown once, when the webhook is created
+    let owner = test.login(OWNER).await?;
+    let mut res = owner
+        .post("https://example.com/api/v1/webhooks")
+        .body(webhook)
+        .await?;
+    assert_eq!(201, res.status());
+    let created: serde_json::Value = res.body_json().await?;
+    assert_eq!("s3cret", created["secret"]);
+
+    let listed: Vec<serde_json::Value> = owner
+        .get("https://example.com/api/v1/webhooks")
+        .recv_json()
+        .await?;
+    assert_eq!(1, listed.len());
+    assert_eq!(created["id"], listed[0]["id"]);
+    assert!(listed[0].get("secret").is_none());
+
+    let other = test.login(OTHER).await?;
+    let listed: Vec<serde_json::Value> = other
+        .get("https://example.com/api/v1/webhooks")
+        .recv_json()
+        .await?;
+    assert!(listed.is_empty());
+
+    let url = format!(
+        "https://example.com/api/v1/webhooks/{}",
+        created["id"].as_str().unwrap()
+    );
+    for (client, status) in [(&anonymous, 401), (&other, 404), (&owner, 204)] {
+        let res = client.delete(&url).await?;
+        assert_eq!(status, res.status());
+    }
+
+    Ok(())
+}
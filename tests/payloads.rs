@@ -0,0 +1,407 @@
+mod common;
+
+use async_std::task::block_on;
+use common::{fixtures, TestApp};
+use proptest::prelude::*;
+use proptest::test_runner::{Config, TestCaseError, TestRunner};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fmt::Display;
+use tide::prelude::*;
+use tide_basic_crud::import::ImportReport;
+use tide_basic_crud::{BatchResponse, Dino};
+use uuid::Uuid;
+
+// the generated requests sent to each endpoint; a panic in a handler unwinds into the runner,
+// which fails the test with the smallest payload that still panics
+static CASES: u32 = 96;
+
+// the body of the api errors
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    error: String,
+    status: u16,
+}
+
+fn runner() -> TestRunner {
+    TestRunner::new(Config {
+        cases: CASES,
+        failure_persistence: None,
+        ..Config::default()
+    })
+}
+
+fn fail(e: impl Display) -> TestCaseError {
+    TestCaseError::fail(e.to_string())
+}
+
+// names a database, a terminal or a browser could trip on
+fn odd_text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        Just(" ".to_string()),
+        Just("\u{0}".to_string()),
+        Just("🦖🦕".to_string()),
+        Just("\u{202e}suruasonnaryT".to_string()),
+        Just("e\u{301}\u{301}\u{301}".to_string()),
+        Just("\u{feff}\u{200b}".to_string()),
+        Just("\u{10ffff}".to_string()),
+        Just("'; DROP TABLE dinos; --".to_string()),
+        Just("<script>alert(1)</script>".to_string()),
+        Just("\"\\/\n\t".to_string()),
+        Just("T".repeat(255)),
+        Just("T".repeat(10_000)),
+        any::<String>(),
+    ]
+}
+
+fn odd_weight() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        any::<i32>().prop_map(|weight| json!(weight)),
+        Just(json!(i32::MIN)),
+        Just(json!(i32::MAX)),
+        Just(json!(i64::from(i32::MAX) + 1)),
+        any::<i64>().prop_map(|weight| json!(weight)),
+        any::<u64>().prop_map(|weight| json!(weight)),
+        any::<f64>().prop_map(|weight| json!(weight)),
+        Just(json!(-0.0)),
+        Just(json!("500")),
+    ]
+}
+
+// a value of any type, for the fields of the wrong type and the extra ones
+fn any_value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(|n| json!(n)),
+        any::<f64>().prop_map(|n| json!(n)),
+        odd_text().prop_map(Value::String),
+    ];
+    leaf.prop_recursive(2, 8, 4, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..4).prop_map(Value::Array),
+            prop::collection::btree_map("[a-z_]{1,8}", inner, 0..4)
+                .prop_map(|fields| Value::Object(fields.into_iter().collect())),
+        ]
+    })
+}
+
+// a field of a dino: mostly one of the given values, sometimes missing or of another type
+fn field(value: BoxedStrategy<Value>) -> impl Strategy<Value = Option<Value>> {
+    prop_oneof![
+        8 => value.prop_map(Some),
+        1 => Just(None),
+        1 => any_value().prop_map(Some),
+    ]
+}
+
+fn dino_payload() -> impl Strategy<Value = Value> {
+    let id = prop_oneof![
+        any::<u128>().prop_map(|id| json!(Uuid::from_u128(id))),
+        odd_text().prop_map(Value::String),
+    ];
+    let diet = prop_oneof![
+        Just(json!("carnivorous")),
+        Just(json!("herbivorous")),
+        odd_text().prop_map(Value::String),
+    ];
+    let extra = prop::collection::btree_map("[a-z_]{1,12}", any_value(), 0..3);
+
+    (
+        field(id.boxed()),
+        field(odd_text().prop_map(Value::String).boxed()),
+        field(odd_weight().boxed()),
+        field(diet.boxed()),
+        extra,
+    )
+        .prop_map(|(id, name, weight, diet, extra)| {
+            let mut dino: Map<String, Value> = extra.into_iter().collect();
+            for (key, value) in [
+                ("id", id),
+                ("name", name),
+                ("weight", weight),
+                ("diet", diet),
+            ] {
+                match value {
+                    Some(value) => dino.insert(key.to_string(), value),
+                    None => dino.remove(key),
+                };
+            }
+            Value::Object(dino)
+        })
+}
+
+// mostly dinos, sometimes not even JSON
+fn body() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        8 => dino_payload().prop_map(|dino| serde_json::to_vec(&dino).unwrap()),
+        1 => dino_payload().prop_flat_map(|dino| {
+            let bytes = serde_json::to_vec(&dino).unwrap();
+            let len = bytes.len();
+            (Just(bytes), 0..len).prop_map(|(bytes, end)| bytes[..end].to_vec())
+        }),
+        1 => prop::collection::vec(any::<u8>(), 0..64),
+    ]
+}
+
+// ids the router matches but that aren't uuids, some percent-encoded
+fn malformed_id() -> impl Strategy<Value = String> {
+    let raw = "[a-zA-Z0-9_~!$&'()*+,;=:@-]{1,64}";
+    let encoded = "\\PC{1,16}".prop_map(|id: String| {
+        id.bytes()
+            .map(|byte| format!("%{:02X}", byte))
+            .collect::<String>()
+    });
+    prop_oneof![
+        Just("00000000-0000-0000-0000-00000000000g".to_string()),
+        Just("a".repeat(1_000)),
+        raw.prop_map(String::from),
+        encoded,
+    ]
+    .prop_filter("a uuid or a route of its own", |id| {
+        Uuid::parse_str(id).is_err()
+            && !["export", "events", "ws", "trash", "batch", "import"].contains(&id.as_str())
+    })
+}
+
+// the statuses the OpenAPI document gives for a path of the api
+fn documented(spec: &Value, method: &str, path: &str) -> Vec<u16> {
+    spec["paths"][path][method]["responses"]
+        .as_object()
+        .unwrap_or_else(|| panic!("{} {} is not documented", method, path))
+        .keys()
+        .map(|status| status.parse().unwrap())
+        .collect()
+}
+
+async fn spec(test: &TestApp) -> tide::Result<Value> {
+    test.client()
+        .get("https://example.com/openapi.json")
+        .recv_json()
+        .await
+}
+
+// the status is a documented one, with a dino or a well-formed error as body
+async fn check(
+    mut res: surf::Response,
+    statuses: &[u16],
+    case: &str,
+) -> Result<u16, TestCaseError> {
+    let status = res.status() as u16;
+    prop_assert!(
+        statuses.contains(&status),
+        "{}: undocumented status {}",
+        case,
+        status
+    );
+
+    let body = res.body_bytes().await.map_err(fail)?;
+    if status >= 400 {
+        let error: ApiError = serde_json::from_slice(&body)
+            .map_err(|e| fail(format!("{}: malformed error body, {}", case, e)))?;
+        prop_assert_eq!(status, error.status, "{}", case);
+        prop_assert!(!error.error.is_empty(), "{}: empty error", case);
+    } else if !body.is_empty() {
+        serde_json::from_slice::<Dino>(&body)
+            .map_err(|e| fail(format!("{}: malformed dino, {}", case, e)))?;
+    }
+    Ok(status)
+}
+
+#[test]
+fn generated_dinos_are_created_or_rejected() -> tide::Result<()> {
+    let test = block_on(TestApp::new());
+    let statuses = documented(&block_on(spec(&test))?, "post", "/dinos");
+    let client = test.client();
+
+    runner()
+        .run(&body(), |body| {
+            block_on(async {
+                let res = client
+                    .post("https://example.com/api/v1/dinos")
+                    .content_type("application/json")
+                    .body(body.clone())
+                    .await
+                    .map_err(fail)?;
+                let case = format!("POST {}", String::from_utf8_lossy(&body));
+                check(res, &statuses, &case).await?;
+                Ok(())
+            })
+        })
+        .map_err(|e| tide::Error::from_str(500, e.to_string()))
+}
+
+#[test]
+fn generated_dinos_replace_or_are_rejected() -> tide::Result<()> {
+    let test = block_on(TestApp::new());
+    let statuses = documented(&block_on(spec(&test))?, "put", "/dinos/{id}");
+    let client = test.client();
+
+    runner()
+        .run(&body(), |body| {
+            block_on(async {
+                let dino = fixtures::dino()
+                    .name("test_payloads")
+                    .insert(test.db_pool())
+                    .await
+                    .map_err(fail)?;
+                let res = client
+                    .put(format!("https://example.com/api/v1/dinos/{}", dino.id))
+                    .content_type("application/json")
+                    .body(body.clone())
+                    .await
+                    .map_err(fail)?;
+                let case = format!("PUT {}", String::from_utf8_lossy(&body));
+                check(res, &statuses, &case).await?;
+                Ok(())
+            })
+        })
+        .map_err(|e| tide::Error::from_str(500, e.to_string()))
+}
+
+// the text fields every write of a dino refuses
+fn valid_text(text: &str) -> bool {
+    !text.contains('\0') && text.chars().count() <= 255
+}
+
+// a dino of the right shape with odd names and diets
+fn odd_dino() -> impl Strategy<Value = (String, String)> {
+    (odd_text(), odd_text())
+}
+
+#[test]
+fn generated_dinos_in_a_batch_are_applied_or_rejected() -> tide::Result<()> {
+    let test = block_on(TestApp::new());
+    let client = test.client();
+
+    runner()
+        .run(&(odd_dino(), odd_dino()), |(created, updated)| {
+            block_on(async {
+                let dino = fixtures::dino()
+                    .name("test_payloads")
+                    .insert(test.db_pool())
+                    .await
+                    .map_err(fail)?;
+                let operations = json!([
+                    { "op": "create", "dino": { "name": created.0, "weight": 50, "diet": created.1 } },
+                    { "op": "update", "id": dino.id, "dino": { "name": updated.0, "weight": 50, "diet": updated.1 } },
+                ]);
+                let mut res = client
+                    .post("https://example.com/api/v1/dinos/batch")
+                    .body(json!({ "atomic": false, "operations": operations }))
+                    .await
+                    .map_err(fail)?;
+                let case = format!("batch {}", operations);
+                prop_assert_eq!(200, res.status() as u16, "{}", case);
+
+                let response: BatchResponse = res.body_json().await.map_err(fail)?;
+                let expected = [(created, 201), (updated, 200)];
+                for (result, ((name, diet), status)) in response.results.iter().zip(expected) {
+                    if valid_text(&name) && valid_text(&diet) {
+                        prop_assert_eq!(status, result.status, "{}: {:?}", case, result.error);
+                    } else {
+                        prop_assert_eq!(422, result.status, "{}: {:?}", case, result.error);
+                    }
+                }
+                Ok(())
+            })
+        })
+        .map_err(|e| tide::Error::from_str(500, e.to_string()))
+}
+
+#[test]
+fn generated_dinos_are_imported_or_rejected() -> tide::Result<()> {
+    let test = block_on(TestApp::new());
+    let client = test.client();
+
+    runner()
+        .run(&prop::collection::vec(odd_dino(), 1..4), |dinos| {
+            block_on(async {
+                let body: String = dinos
+                    .iter()
+                    .map(|(name, diet)| {
+                        format!("{}\n", json!({ "name": name, "weight": 50, "diet": diet }))
+                    })
+                    .collect();
+                let mut res = client
+                    .post("https://example.com/api/v1/dinos/import?format=ndjson")
+                    .body(body.clone())
+                    .content_type("application/x-ndjson")
+                    .await
+                    .map_err(fail)?;
+                let case = format!("import {}", body);
+                let status = res.status() as u16;
+
+                let report: ImportReport = res.body_json().await.map_err(fail)?;
+                let invalid = dinos
+                    .iter()
+                    .filter(|(name, diet)| !valid_text(name) || !valid_text(diet))
+                    .count();
+                prop_assert_eq!(dinos.len() - invalid, report.created, "{}", case);
+                prop_assert_eq!(invalid, report.errors.len(), "{}", case);
+                prop_assert_eq!(if invalid == 0 { 200 } else { 422 }, status, "{}", case);
+                for error in &report.errors {
+                    prop_assert!(
+                        error.error.starts_with("the fields of a dino can't"),
+                        "{}: {}",
+                        case,
+                        error.error
+                    );
+                }
+                Ok(())
+            })
+        })
+        .map_err(|e| tide::Error::from_str(500, e.to_string()))
+}
+
+#[test]
+fn malformed_ids_are_rejected() -> tide::Result<()> {
+    let test = block_on(TestApp::new());
+    let spec = block_on(spec(&test))?;
+    // logged in, the webhooks are only served to their owners
+    let client = block_on(test.login("test_payloads"))?;
+    let dino = json!({ "id": Uuid::new_v4(), "name": "test_payloads", "weight": 50, "diet": "carnivorous" });
+
+    let routes = [
+        ("get", "/dinos/{id}"),
+        ("put", "/dinos/{id}"),
+        ("patch", "/dinos/{id}"),
+        ("delete", "/dinos/{id}"),
+        ("get", "/dinos/{id}/history"),
+        ("get", "/dinos/{id}/revisions"),
+        ("post", "/dinos/{id}/revisions/{rev}/revert"),
+        ("post", "/dinos/{id}/restore"),
+        ("delete", "/webhooks/{id}"),
+        ("get", "/webhooks/{id}/deliveries"),
+    ];
+
+    runner()
+        .run(&malformed_id(), |id| {
+            block_on(async {
+                for (method, path) in routes {
+                    let url = format!(
+                        "https://example.com/api/v1{}",
+                        path.replace("{id}", &id).replace("{rev}", "1")
+                    );
+                    let req = match method {
+                        "get" => client.get(&url),
+                        "put" => client.put(&url).body(dino.clone()),
+                        "patch" => client
+                            .patch(&url)
+                            .content_type("application/merge-patch+json")
+                            .body(json!({ "weight": 60 })),
+                        "delete" => client.delete(&url),
+                        _ => client.post(&url),
+                    };
+                    let res = req.await.map_err(fail)?;
+
+                    let case = format!("{} {}", method.to_uppercase(), url);
+                    let status = check(res, &documented(&spec, method, path), &case).await?;
+                    prop_assert_eq!(400, status, "{}", case);
+                }
+                Ok(())
+            })
+        })
+        .map_err(|e| tide::Error::from_str(500, e.to_string()))
+}
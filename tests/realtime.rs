@@ -0,0 +1,450 @@
+mod common;
+
+use async_tungstenite::WebSocketStream;
+use common::TestApp;
+use tide::prelude::*;
+use tide::Error;
+use tide_basic_crud::{handlers, webhooks, Config, Dino};
+
+#[async_std::test]
+async fn dino_changes_are_streamed_to_every_instance() -> tide::Result<()> {
+    use async_std::io::prelude::BufReadExt;
+    use async_std::stream::StreamExt;
+
+    let test = TestApp::new().await;
+    let writer = test.client();
+    let reader = surf::Client::with_http_client(test.instance().await);
+
+    let mut res = reader
+        .get("https://example.com/api/v1/dinos/events")
+        .await?;
+    assert_eq!(200, res.status());
+    assert_eq!(
+        Some("text/event-stream".to_string()),
+        res.content_type().map(|m| m.essence().to_string())
+    );
+    let mut lines = res.take_body().lines();
+
+    // let both instances start listening
+    async_std::task::sleep(std::time::Duration::from_millis(500)).await;
+
+    let mut res = writer
+        .post("https://example.com/api/v1/dinos")
+        .body(serde_json::json!({ "name": "test_events", "weight": 50, "diet": "carnivorous" }))
+        .await?;
+    assert_eq!(201, res.status());
+    let dino: Dino = res.body_json().await?;
+    let res = writer
+        .delete(format!("https://example.com/api/v1/dinos/{}", dino.id))
+        .await?;
+    assert_eq!(204, res.status());
+
+    let mut events = vec![];
+    let mut event = String::new();
+    while events.len() < 2 {
+        let line = async_std::future::timeout(std::time::Duration::from_secs(5), lines.next())
+            .await
+            .expect("no dino event received")
+            .unwrap()?;
+        if let Some(name) = line.strip_prefix("event:") {
+            event = name.trim().to_string();
+        } else if let Some(data) = line.strip_prefix("data:") {
+            let changed: Dino = serde_json::from_str(data.trim())?;
+            if changed.id == dino.id {
+                events.push(event.clone());
+            }
+        }
+    }
+    assert_eq!(vec!["created", "deleted"], events);
+
+    Ok(())
+}
+
+// A websocket to a listening instance of the app, sent from a page of the app when `from_page`.
+async fn websocket(
+    test: &TestApp,
+    from_page: bool,
+) -> tide::Result<WebSocketStream<async_std::net::TcpStream>> {
+    use async_tungstenite::tungstenite::client::IntoClientRequest;
+    use tide::listener::Listener;
+
+    let mut listener = test.app.clone().bind("127.0.0.1:0").await?;
+    let address = listener.info()[0].connection().replace("http://", "");
+    async_std::task::spawn(async move { listener.accept().await });
+
+    let mut request = format!("ws://{}/api/v1/dinos/ws", address)
+        .into_client_request()
+        .map_err(|e| Error::from_str(500, e.to_string()))?;
+    if from_page {
+        let origin = format!("http://{}", address).parse()?;
+        request.headers_mut().insert("Origin", origin);
+    }
+
+    let stream = async_std::net::TcpStream::connect(&address).await?;
+    let (socket, _) = async_tungstenite::client_async(request, stream)
+        .await
+        .map_err(|e| Error::from_str(500, e.to_string()))?;
+    Ok(socket)
+}
+
+#[async_std::test]
+async fn dinos_over_a_websocket() -> tide::Result<()> {
+    use async_std::stream::StreamExt;
+    use async_tungstenite::tungstenite::Message;
+    use futures_util::SinkExt;
+
+    let test = TestApp::new().await;
+
+    let res = test
+        .client()
+        .get("https://example.com/api/v1/dinos/ws")
+        .await?;
+    assert_eq!(426, res.status());
+
+    // a page of another site can't open it
+    let res = test
+        .client()
+        .get("https://example.com/api/v1/dinos/ws")
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .header("Origin", "https://evil.example")
+        .await?;
+    assert_eq!(403, res.status());
+
+    let mut socket = websocket(&test, true).await?;
+
+    let subscribe = json!({
+        "type": "subscribe",
+        "id": "herbivores",
+        "filter": { "diet": "test_ws_herbivorous" },
+    });
+    socket.send(Message::Text(subscribe.to_string())).await?;
+    socket
+        .send(Message::Text(json!({ "type": "ping" }).to_string()))
+        .await?;
+    // let the instance start listening
+    async_std::task::sleep(std::time::Duration::from_millis(500)).await;
+    for (reference, diet) in [("c1", "test_ws_carnivorous"), ("c2", "test_ws_herbivorous")] {
+        socket
+            .send(Message::Text(
+                json!({
+                    "type": "command",
+                    "ref": reference,
+                    "op": "create",
+                    "dino": { "name": "test_ws", "weight": 50, "diet": diet },
+                })
+                .to_string(),
+            ))
+            .await?;
+    }
+
+    let mut messages = vec![];
+    while messages.len() < 5 {
+        let message = async_std::future::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await
+            .expect("no websocket message received")
+            .unwrap()?;
+        if let Message::Text(text) = message {
+            messages.push(serde_json::from_str::<serde_json::Value>(&text)?);
+        }
+    }
+
+    assert_eq!(
+        json!({ "type": "subscribed", "id": "herbivores" }),
+        messages[0]
+    );
+    assert_eq!(json!({ "type": "pong" }), messages[1]);
+    for (message, reference) in messages[2..4].iter().zip(["c1", "c2"]) {
+        assert_eq!("result", message["type"]);
+        assert_eq!(reference, message["ref"]);
+        assert_eq!(201, message["status"]);
+    }
+    // only the herbivore matches the subscription
+    assert_eq!("event", messages[4]["type"]);
+    assert_eq!("herbivores", messages[4]["subscription"]);
+    assert_eq!("created", messages[4]["kind"]);
+    assert_eq!(messages[3]["dino"], messages[4]["dino"]);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn websocket_subscriptions_are_capped() -> tide::Result<()> {
+    use async_std::stream::StreamExt;
+    use async_tungstenite::tungstenite::Message;
+    use futures_util::SinkExt;
+
+    let test = TestApp::new().await;
+    let mut socket = websocket(&test, false).await?;
+
+    for id in 0..33 {
+        let subscribe = json!({ "type": "subscribe", "id": id.to_string() });
+        socket.send(Message::Text(subscribe.to_string())).await?;
+    }
+
+    let mut messages = vec![];
+    while messages.len() < 33 {
+        let message = async_std::future::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await
+            .expect("no websocket message received")
+            .unwrap()?;
+        if let Message::Text(text) = message {
+            messages.push(serde_json::from_str::<serde_json::Value>(&text)?);
+        }
+    }
+    assert!(messages[..32].iter().all(|m| m["type"] == "subscribed"));
+    assert_eq!(
+        json!({ "type": "error", "error": "at most 32 subscriptions" }),
+        messages[32]
+    );
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn dino_changes_are_delivered_to_webhooks() -> tide::Result<()> {
+    use std::sync::{Arc, Mutex};
+    use tide::listener::Listener;
+
+    // a receiver failing every other request
+    type Received = Vec<((String, String), Vec<u8>)>;
+    let received: Arc<Mutex<Received>> = Arc::default();
+    let mut receiver = tide::with_state(received.clone());
+    receiver
+        .at("/hook")
+        .post(|mut req: tide::Request<Arc<Mutex<Received>>>| async move {
+            let body = req.body_bytes().await?;
+            let headers = (
+                req.header("X-Webhook-Event").unwrap().as_str().to_string(),
+                req.header("X-Webhook-Signature")
+                    .unwrap()
+                    .as_str()
+                    .to_string(),
+            );
+            let mut received = req.state().lock().unwrap();
+            received.push((headers, body));
+            let status = if received.len() % 2 == 1 { 500 } else { 200 };
+            Ok(tide::Response::new(status))
+        });
+    let mut listener = receiver.bind("127.0.0.1:0").await?;
+    let url = format!("{}/hook", listener.info()[0].connection());
+    async_std::task::spawn(async move { listener.accept().await });
+
+    // the receiver listens on the loopback
+    let test = TestApp::with_config(Config {
+        webhook_allow_private_targets: true,
+        ..common::config()
+    })
+    .await;
+    let app = test.login("test_webhook_owner").await?;
+    let client = surf::Client::new();
+
+    let mut res = app
+        .post("https://example.com/api/v1/webhooks")
+        .body(json!({
+            "url": url,
+            "events": ["created", "deleted"],
+            "diet": "test_webhook_diet",
+            "secret": "s3cret",
+        }))
+        .await?;
+    assert_eq!(201, res.status());
+    let webhook: handlers::webhook::Webhook = res.body_json().await?;
+
+    let mut res = app
+        .post("https://example.com/api/v1/dinos")
+        .body(json!({ "name": "test_webhook", "weight": 50, "diet": "test_webhook_diet" }))
+        .await?;
+    assert_eq!(201, res.status());
+    let dino: Dino = res.body_json().await?;
+
+    // the first attempt fails and is retried right away, the second one succeeds
+    let retry = webhooks::RetryPolicy {
+        base_secs: 0,
+        max_secs: 0,
+        max_attempts: 2,
+    };
+    webhooks::dispatch(test.db_pool(), &client, &retry, true).await?;
+    webhooks::dispatch(test.db_pool(), &client, &retry, true).await?;
+
+    // a failed delete isn't retried
+    let res = app
+        .delete(format!("https://example.com/api/v1/dinos/{}", dino.id))
+        .await?;
+    assert_eq!(204, res.status());
+    let no_retry = webhooks::RetryPolicy {
+        max_attempts: 1,
+        ..retry
+    };
+    webhooks::dispatch(test.db_pool(), &client, &no_retry, true).await?;
+    webhooks::dispatch(test.db_pool(), &client, &no_retry, true).await?;
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(3, received.len());
+    for ((event, signature), body) in &received {
+        assert_eq!(&webhooks::sign("s3cret", body), signature);
+        let body: serde_json::Value = serde_json::from_slice(body)?;
+        assert_eq!(event, &body["event"]);
+        assert_eq!(dino.id.to_string(), body["dino"]["id"]);
+    }
+    let events: Vec<_> = received
+        .iter()
+        .map(|((event, _), _)| event.as_str())
+        .collect();
+    assert_eq!(vec!["created", "created", "deleted"], events);
+
+    let mut res = app
+        .get(format!(
+            "https://example.com/api/v1/webhooks/{}/deliveries",
+            webhook.id
+        ))
+        .await?;
+    assert_eq!(200, res.status());
+    let deliveries: Vec<handlers::webhook::Delivery> = res.body_json().await?;
+    let log: Vec<_> = deliveries
+        .iter()
+        .map(|d| {
+            (
+                d.event.as_str(),
+                d.status.as_str(),
+                d.attempts,
+                d.last_status,
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            ("deleted", "dead", 1, Some(500)),
+            ("created", "delivered", 2, Some(200)),
+        ],
+        log
+    );
+
+    let res = app
+        .delete(format!(
+            "https://example.com/api/v1/webhooks/{}",
+            webhook.id
+        ))
+        .await?;
+    assert_eq!(204, res.status());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn webhooks_only_deliver_to_public_addresses() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let app = test.login("test_webhook_owner").await?;
+
+    for url in [
+        "http://127.0.0.1/hook",
+        "http://localhost:8080/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://10.0.0.1/hook",
+        "http://192.168.1.1/hook",
+        "http://[::1]/hook",
+        "http://[::ffff:127.0.0.1]/hook",
+        "http://[fd00::1]/hook",
+        "http://0.0.0.0/hook",
+    ] {
+        let res = app
+            .post("https://example.com/api/v1/webhooks")
+            .body(json!({ "url": url }))
+            .await?;
+        assert_eq!(422, res.status(), "{}", url);
+    }
+
+    // a host resolving to a private address since the webhook was created isn't delivered to
+    let webhook = handlers::webhook::Webhook {
+        id: uuid::Uuid::new_v4(),
+        user_id: "test_webhook_owner".to_string(),
+        url: "http://127.0.0.1:9/hook".to_string(),
+        secret: "s3cret".to_string(),
+        events: vec!["created".to_string()],
+        diet: Some("test_webhook_diet".to_string()),
+        created_at: chrono::Utc::now(),
+    };
+    handlers::webhook::create(&webhook, test.db_pool()).await?;
+    let res = app
+        .post("https://example.com/api/v1/dinos")
+        .body(json!({ "name": "test_webhook", "weight": 50, "diet": "test_webhook_diet" }))
+        .await?;
+    assert_eq!(201, res.status());
+
+    let retry = webhooks::RetryPolicy {
+        base_secs: 0,
+        max_secs: 0,
+        max_attempts: 3,
+    };
+    let client = surf::Client::new();
+    let log = format!(
+        "https://example.com/api/v1/webhooks/{}/deliveries",
+        webhook.id
+    );
+
+    webhooks::dispatch(test.db_pool(), &client, &retry, false).await?;
+    let deliveries: Vec<handlers::webhook::Delivery> = app.get(&log).recv_json().await?;
+    assert_eq!(1, deliveries.len());
+    assert_eq!(1, deliveries[0].attempts);
+    assert_eq!(
+        Some("the url must only resolve to public addresses"),
+        deliveries[0].last_error.as_deref()
+    );
+
+    // allowed, the error of the refused connection isn't shown to the owner either
+    webhooks::dispatch(test.db_pool(), &client, &retry, true).await?;
+    let deliveries: Vec<handlers::webhook::Delivery> = app.get(&log).recv_json().await?;
+    assert_eq!(2, deliveries[0].attempts);
+    assert_eq!(None, deliveries[0].last_status);
+    assert_eq!(
+        Some("the request failed"),
+        deliveries[0].last_error.as_deref()
+    );
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn claimed_webhook_deliveries_are_skipped() -> tide::Result<()> {
+    let test = TestApp::new().await;
+    let webhook = handlers::webhook::Webhook {
+        id: uuid::Uuid::new_v4(),
+        user_id: "test_webhook_owner".to_string(),
+        url: "http://93.184.215.14/hook".to_string(),
+        secret: "s3cret".to_string(),
+        events: vec!["created".to_string()],
+        diet: Some("test_webhook_diet".to_string()),
+        created_at: chrono::Utc::now(),
+    };
+    handlers::webhook::create(&webhook, test.db_pool()).await?;
+    let res = test
+        .client()
+        .post("https://example.com/api/v1/dinos")
+        .body(json!({ "name": "test_webhook", "weight": 50, "diet": "test_webhook_diet" }))
+        .await?;
+    assert_eq!(201, res.status());
+
+    // claimed by another instance, which is sending it
+    let claimed = handlers::webhook::claim(32, 600, test.db_pool()).await?;
+    assert_eq!(1, claimed.len());
+    assert!(handlers::webhook::claim(32, 600, test.db_pool())
+        .await?
+        .is_empty());
+    let retry = webhooks::RetryPolicy {
+        base_secs: 0,
+        max_secs: 0,
+        max_attempts: 1,
+    };
+    let sent = webhooks::dispatch(test.db_pool(), &surf::Client::new(), &retry, false).await?;
+    assert_eq!(0, sent);
+
+    // the claim of an instance that never recorded the attempt runs out
+    sqlx::query("UPDATE webhook_deliveries SET next_attempt_at = now()")
+        .execute(test.db_pool())
+        .await?;
+    let claimed = handlers::webhook::claim(32, 600, test.db_pool()).await?;
+    assert_eq!(1, claimed.len());
+
+    Ok(())
+}
@@ -0,0 +1,435 @@
+mod common;
+
+use common::{fixtures, TestDb};
+use std::sync::Arc;
+use tide::prelude::*;
+use tide::Server;
+use tide_basic_crud::repository::{self, DinoRepository};
+use tide_basic_crud::{server, standalone_server, Dino, State, TrashedDino};
+use uuid::Uuid;
+
+// The dino api of `app` on top of `dinos`, the same whatever the backend.
+async fn check_dino_api(app: Server<State>, dinos: Arc<dyn DinoRepository>) -> tide::Result<()> {
+    let client = surf::Client::with_http_client(app);
+
+    let dino = fixtures::dino().name("test_repository").build();
+    let res = client
+        .post("https://example.com/api/v1/dinos")
+        .body(serde_json::to_string(&dino)?)
+        .await?;
+    assert_eq!(201, res.status());
+    let res = client
+        .post("https://example.com/api/v1/dinos")
+        .body(serde_json::to_string(&dino)?)
+        .await?;
+    assert_eq!(409, res.status());
+
+    let url = format!("https://example.com/api/v1/dinos/{}", dino.id);
+    let mut res = client
+        .patch(&url)
+        .content_type("application/merge-patch+json")
+        .body(json!({ "weight": 600 }))
+        .await?;
+    assert_eq!(200, res.status());
+    let patched: Dino = res.body_json().await?;
+    assert_eq!(
+        (600, "test_repository"),
+        (patched.weight, patched.name.as_str())
+    );
+
+    let mut updated = patched.clone();
+    updated.diet = String::from("herbivorous");
+    let res = client
+        .put(&url)
+        .body(serde_json::to_string(&updated)?)
+        .await?;
+    assert_eq!(200, res.status());
+
+    assert_eq!(204, client.delete(&url).await?.status());
+    assert_eq!(404, client.get(&url).await?.status());
+    assert!(!dinos.list().await?.iter().any(|d| d.id == dino.id));
+    let trash = dinos.trash("").await?;
+    assert!(trash.iter().any(|d| d.id == dino.id));
+
+    let res = client.post(format!("{}/restore", url)).await?;
+    assert_eq!(200, res.status());
+    let mut res = client.get(&url).await?;
+    assert_eq!(200, res.status());
+    let restored: Dino = res.body_json().await?;
+    assert_eq!(
+        (600, "herbivorous"),
+        (restored.weight, restored.diet.as_str())
+    );
+
+    let mut exported = client
+        .get("https://example.com/api/v1/dinos/export?format=ndjson")
+        .await?;
+    assert!(exported.body_string().await?.contains(&dino.id.to_string()));
+
+    Ok(())
+}
+
+// The standalone app on top of `dinos`: the dino api, and none of the routes needing Postgres.
+async fn check_standalone(dinos: Arc<dyn DinoRepository>) -> tide::Result<()> {
+    let app = standalone_server(dinos.clone(), common::config()).await;
+    let client = surf::Client::with_http_client(app.clone());
+
+    let id = Uuid::new_v4();
+    for (method, url) in [
+        ("get", format!("/api/v1/dinos/{}/history", id)),
+        ("get", format!("/api/v1/dinos/{}/revisions", id)),
+        ("post", "/api/v1/dinos/batch".to_string()),
+        ("post", "/api/v1/dinos/import".to_string()),
+        ("get", "/api/v1/dinos/events".to_string()),
+        ("get", "/api/v1/webhooks".to_string()),
+        ("get", "/auth/google".to_string()),
+        ("get", format!("/dinos/{}/edit", id)),
+        ("get", "/metrics".to_string()),
+    ] {
+        let url = format!("https://example.com{}", url);
+        let res = match method {
+            "get" => client.get(&url).await?,
+            _ => client.post(&url).await?,
+        };
+        // `/dinos/batch` or `/dinos/events` are still `/dinos/:id` to the router, a malformed id
+        // or another method
+        assert!(
+            [400, 404, 405].contains(&(res.status() as u16)),
+            "{} {}: {}",
+            method,
+            url,
+            res.status()
+        );
+    }
+
+    check_dino_api(app, dinos).await
+}
+
+#[async_std::test]
+async fn dinos_in_memory() -> tide::Result<()> {
+    check_standalone(Arc::new(repository::MemoryDinoRepository::new())).await
+}
+
+#[async_std::test]
+async fn dinos_in_postgres() -> tide::Result<()> {
+    let db = TestDb::new().await;
+    let app = server(db.db_pool.clone(), common::config()).await;
+    let dinos = Arc::new(repository::PgDinoRepository::new(db.db_pool.clone()));
+    check_dino_api(app, dinos).await
+}
+
+// An SQLite file of the temporary directory, removed with it.
+#[cfg(feature = "sqlite")]
+struct SqliteFile(std::path::PathBuf);
+
+#[cfg(feature = "sqlite")]
+impl SqliteFile {
+    fn new() -> SqliteFile {
+        SqliteFile(std::env::temp_dir().join(format!("tide-{}.db", Uuid::new_v4())))
+    }
+
+    async fn repository(&self) -> tide::Result<Arc<dyn DinoRepository>> {
+        let db_pool =
+            repository::sqlite::make_db_pool(&format!("sqlite:{}", self.0.display())).await?;
+        Ok(Arc::new(repository::SqliteDinoRepository::new(db_pool)))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Drop for SqliteFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_std::test]
+async fn dinos_in_sqlite() -> tide::Result<()> {
+    let file = SqliteFile::new();
+    check_standalone(file.repository().await?).await
+}
+
+// The app on top of each backend, with the repository it uses.
+struct Backend {
+    name: &'static str,
+    app: Server<State>,
+    dinos: Arc<dyn DinoRepository>,
+    _db: Option<TestDb>,
+    #[cfg(feature = "sqlite")]
+    _file: Option<SqliteFile>,
+}
+
+impl Backend {
+    async fn all() -> tide::Result<Vec<Backend>> {
+        let mut backends = Vec::new();
+
+        let dinos: Arc<dyn DinoRepository> = Arc::new(repository::MemoryDinoRepository::new());
+        backends.push(Backend {
+            name: "memory",
+            app: standalone_server(dinos.clone(), common::config()).await,
+            dinos,
+            _db: None,
+            #[cfg(feature = "sqlite")]
+            _file: None,
+        });
+
+        let db = TestDb::new().await;
+        backends.push(Backend {
+            name: "postgres",
+            app: server(db.db_pool.clone(), common::config()).await,
+            dinos: Arc::new(repository::PgDinoRepository::new(db.db_pool.clone())),
+            _db: Some(db),
+            #[cfg(feature = "sqlite")]
+            _file: None,
+        });
+
+        #[cfg(feature = "sqlite")]
+        {
+            let file = SqliteFile::new();
+            let dinos = file.repository().await?;
+            backends.push(Backend {
+                name: "sqlite",
+                app: standalone_server(dinos.clone(), common::config()).await,
+                dinos,
+                _db: None,
+                _file: Some(file),
+            });
+        }
+
+        Ok(backends)
+    }
+
+    fn client(&self) -> surf::Client {
+        surf::Client::with_http_client(self.app.clone())
+    }
+
+    async fn insert(&self, dino: fixtures::DinoFixture) -> tide::Result<Dino> {
+        self.dinos.create(dino.build(), &Default::default()).await
+    }
+
+    fn url(&self, id: impl std::fmt::Display) -> String {
+        format!("https://example.com/api/v1/dinos/{}", id)
+    }
+}
+
+#[async_std::test]
+async fn dinos_are_updated_in_every_backend() -> tide::Result<()> {
+    use assert_json_diff::assert_json_eq;
+
+    for backend in Backend::all().await? {
+        let client = backend.client();
+        let dino = backend.insert(fixtures::dino().name("test_update")).await?;
+
+        let mut updated = dino.clone();
+        updated.name = String::from("updated from test");
+        let mut res = client
+            .put(backend.url(dino.id))
+            .body(serde_json::to_string(&updated)?)
+            .await?;
+        assert_eq!(200, res.status(), "{}", backend.name);
+        let d: Dino = res.body_json().await?;
+        assert_json_eq!(updated, d);
+
+        let missing = fixtures::dino().build();
+        let res = client
+            .put(backend.url(missing.id))
+            .body(serde_json::to_string(&missing)?)
+            .await?;
+        assert_eq!(404, res.status(), "{}", backend.name);
+        let res = client
+            .put(backend.url("not-a-uuid"))
+            .body(serde_json::to_string(&missing)?)
+            .await?;
+        assert_eq!(400, res.status(), "{}", backend.name);
+
+        let owned = backend
+            .insert(fixtures::dino().name("test_update").owner("123"))
+            .await?;
+        let mut changed = owned.clone();
+        changed.weight = 600;
+        let res = client
+            .put(backend.url(owned.id))
+            .body(serde_json::to_string(&changed)?)
+            .await?;
+        assert_eq!(401, res.status(), "{}", backend.name);
+        let stored = backend.dinos.get(owned.id).await?.unwrap();
+        assert_eq!(500, stored.weight, "{}", backend.name);
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn dinos_are_patched_in_every_backend() -> tide::Result<()> {
+    for backend in Backend::all().await? {
+        let client = backend.client();
+        let dino = backend.insert(fixtures::dino().name("test_patch")).await?;
+        let url = backend.url(dino.id);
+
+        let mut res = client
+            .patch(&url)
+            .content_type("application/merge-patch+json")
+            .body(json!({ "weight": 600 }))
+            .await?;
+        assert_eq!(200, res.status(), "{}", backend.name);
+        let d: Dino = res.body_json().await?;
+        assert_eq!(600, d.weight, "{}", backend.name);
+
+        let mut res = client
+            .patch(&url)
+            .content_type("application/json-patch+json")
+            .body(json!([
+                { "op": "test", "path": "/name", "value": "test_patch" },
+                { "op": "replace", "path": "/diet", "value": "herbivorous" },
+            ]))
+            .await?;
+        assert_eq!(200, res.status(), "{}", backend.name);
+        let d: Dino = res.body_json().await?;
+        assert_eq!("herbivorous", d.diet, "{}", backend.name);
+
+        // (content type, patch, status)
+        let rejected = [
+            (
+                "application/json-patch+json",
+                json!([{ "op": "test", "path": "/name", "value": "other" }]),
+                409,
+            ),
+            (
+                "application/json-patch+json",
+                json!([{ "op": "remove", "path": "/weight" }]),
+                422,
+            ),
+            (
+                "application/merge-patch+json",
+                json!({ "id": Uuid::new_v4() }),
+                422,
+            ),
+            (
+                "application/merge-patch+json",
+                json!({ "user_id": "123" }),
+                422,
+            ),
+            ("application/json", json!({ "weight": 700 }), 415),
+        ];
+        for (content_type, patch, status) in rejected {
+            let res = client
+                .patch(&url)
+                .content_type(content_type)
+                .body(patch.clone())
+                .await?;
+            assert_eq!(status, res.status(), "{} {}", backend.name, patch);
+        }
+        let stored = backend.dinos.get(dino.id).await?.unwrap();
+        assert_eq!(
+            (600, "herbivorous", None),
+            (stored.weight, stored.diet.as_str(), stored.user_id),
+            "{}",
+            backend.name
+        );
+
+        for (id, status) in [
+            (Uuid::new_v4().to_string(), 404),
+            ("not-a-uuid".to_string(), 400),
+        ] {
+            let res = client
+                .patch(backend.url(id))
+                .content_type("application/merge-patch+json")
+                .body(json!({ "weight": 700 }))
+                .await?;
+            assert_eq!(status, res.status(), "{}", backend.name);
+        }
+
+        let owned = backend
+            .insert(fixtures::dino().name("test_patch").owner("123"))
+            .await?;
+        let res = client
+            .patch(backend.url(owned.id))
+            .content_type("application/merge-patch+json")
+            .body(json!({ "weight": 700 }))
+            .await?;
+        assert_eq!(401, res.status(), "{}", backend.name);
+        let stored = backend.dinos.get(owned.id).await?.unwrap();
+        assert_eq!(500, stored.weight, "{}", backend.name);
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn dinos_are_deleted_in_every_backend() -> tide::Result<()> {
+    for backend in Backend::all().await? {
+        let client = backend.client();
+        let dino = backend.insert(fixtures::dino().name("test_delete")).await?;
+
+        let res = client.delete(backend.url(dino.id)).await?;
+        assert_eq!(204, res.status(), "{}", backend.name);
+        let res = client.get(backend.url(dino.id)).await?;
+        assert_eq!(404, res.status(), "{}", backend.name);
+        let res = client.delete(backend.url(dino.id)).await?;
+        assert_eq!(404, res.status(), "{}", backend.name);
+        let res = client.delete(backend.url("not-a-uuid")).await?;
+        assert_eq!(400, res.status(), "{}", backend.name);
+
+        let owned = backend
+            .insert(fixtures::dino().name("test_delete").owner("123"))
+            .await?;
+        let res = client.delete(backend.url(owned.id)).await?;
+        assert_eq!(401, res.status(), "{}", backend.name);
+        assert!(
+            backend.dinos.get(owned.id).await?.is_some(),
+            "{}",
+            backend.name
+        );
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn dinos_are_restored_in_every_backend() -> tide::Result<()> {
+    for backend in Backend::all().await? {
+        let client = backend.client();
+        let dino = backend.insert(fixtures::dino().name("test_trash")).await?;
+        let owned = backend
+            .insert(fixtures::dino().name("test_trash_owned").owner("123"))
+            .await?;
+        for id in [dino.id, owned.id] {
+            backend.dinos.delete(id, &Default::default()).await?;
+        }
+
+        // only the dinos the user can restore are listed
+        let mut res = client.get("https://example.com/api/v1/dinos/trash").await?;
+        assert_eq!(200, res.status(), "{}", backend.name);
+        let trash: Vec<TrashedDino> = res.body_json().await?;
+        assert!(trash.iter().any(|d| d.id == dino.id), "{}", backend.name);
+        assert!(!trash.iter().any(|d| d.id == owned.id), "{}", backend.name);
+
+        let res = client
+            .post(format!("{}/restore", backend.url(owned.id)))
+            .await?;
+        assert_eq!(401, res.status(), "{}", backend.name);
+
+        let mut res = client
+            .post(format!("{}/restore", backend.url(dino.id)))
+            .await?;
+        assert_eq!(200, res.status(), "{}", backend.name);
+        let restored: Dino = res.body_json().await?;
+        assert_eq!(dino.name, restored.name, "{}", backend.name);
+        assert!(
+            backend.dinos.get(dino.id).await?.is_some(),
+            "{}",
+            backend.name
+        );
+
+        let res = client
+            .post(format!("{}/restore", backend.url(dino.id)))
+            .await?;
+        assert_eq!(404, res.status(), "{}", backend.name);
+        let res = client
+            .post(format!("{}/restore", backend.url("not-a-uuid")))
+            .await?;
+        assert_eq!(400, res.status(), "{}", backend.name);
+    }
+
+    Ok(())
+}